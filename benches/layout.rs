@@ -0,0 +1,134 @@
+//! Layout analysis on synthetic pages: two columns of justified text with a heading,
+//! and tables outlined by ruling lines.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use pdf_render::{tracer::{TextChar, TextSpan}, Fill, TextMode};
+use pdf_text::{bench, Options};
+
+const PAGE: (f32, f32) = (595.0, 842.0);
+const WORD: &str = "lorem";
+
+/// A span of `text` at `(x, y)` (top left) without a font, like a word drawn by one `Tj`.
+fn span(text: &str, x: f32, y: f32, font_size: f32) -> TextSpan {
+    let char_width = 0.5 * font_size;
+    let chars = text.char_indices().map(|(i, _)| TextChar {
+        offset: i,
+        pos: i as f32 * char_width,
+        width: char_width,
+    }).collect();
+    let rect = RectF::new(Vector2F::new(x, y), Vector2F::new(text.len() as f32 * char_width, font_size));
+    TextSpan {
+        rect,
+        width: rect.width(),
+        bbox: None,
+        font_size,
+        font: None,
+        text: text.into(),
+        chars,
+        color: Fill::Solid(0.0, 0.0, 0.0),
+        alpha: 1.0,
+        transform: Transform2F::from_translation(rect.origin()),
+        mode: TextMode::Fill,
+        op_nr: 0,
+    }
+}
+
+/// About `num_spans` words on pages stacked below each other, so big inputs
+/// don't end up with microscopic line spacing.
+fn page(num_spans: usize) -> (Vec<TextSpan>, RectF) {
+    let font_size = 10.0;
+    let line_height = 1.2 * font_size;
+    let word_width = (WORD.len() as f32 + 1.0) * 0.5 * font_size;
+    let column_width = 240.0;
+    let words_per_line = (column_width / word_width) as usize;
+    let lines_per_column = ((PAGE.1 - 100.0) / line_height) as usize;
+
+    let mut spans = vec![span("Heading", 50.0, 30.0, 2.0 * font_size)];
+    let mut n = 0;
+    'a: for page in 0 .. {
+        let top = page as f32 * PAGE.1;
+        for column in 0 .. 2 {
+            let left = 50.0 + column as f32 * (column_width + 20.0);
+            for line in 0 .. lines_per_column {
+                // indent the first line of every paragraph
+                let indent = if line % 8 == 0 { 2.0 * font_size } else { 0.0 };
+                for word in 0 .. words_per_line {
+                    if n == num_spans {
+                        break 'a;
+                    }
+                    let x = left + indent + word as f32 * word_width;
+                    spans.push(span(WORD, x, top + 70.0 + line as f32 * line_height, font_size));
+                    n += 1;
+                }
+            }
+        }
+    }
+    let bottom = spans.iter().map(|s| s.rect.max_y()).fold(PAGE.1, f32::max);
+    (spans, RectF::new(Vector2F::default(), Vector2F::new(PAGE.0, bottom)))
+}
+
+/// A table of `num_rows` rows and four columns of one word each, with a ruling line
+/// around every cell.
+fn ruled_table(num_rows: usize) -> (Vec<TextSpan>, Vec<([f32; 4], f32)>, RectF) {
+    let font_size = 10.0;
+    let (left, top) = (50.0, 50.0);
+    let (cell_width, cell_height) = (100.0, 20.0);
+    let num_cols = 4;
+
+    let mut spans = vec![];
+    for row in 0 .. num_rows {
+        for col in 0 .. num_cols {
+            let x = left + col as f32 * cell_width + 5.0;
+            let y = top + row as f32 * cell_height + 5.0;
+            spans.push(span(WORD, x, y, font_size));
+        }
+    }
+    let right = left + num_cols as f32 * cell_width;
+    let bottom = top + num_rows as f32 * cell_height;
+    let mut lines = vec![];
+    for row in 0 ..= num_rows {
+        let y = top + row as f32 * cell_height;
+        lines.push(([left, y, right, y], 0.5));
+    }
+    for col in 0 ..= num_cols {
+        let x = left + col as f32 * cell_width;
+        lines.push(([x, top, x, bottom], 0.5));
+    }
+    (spans, lines, RectF::new(Vector2F::default(), Vector2F::new(PAGE.0, (bottom + 50.0).max(PAGE.1))))
+}
+
+fn layout(c: &mut Criterion) {
+    let opts = Options::default();
+    let mut build = c.benchmark_group("build");
+    for &size in &[100, 1_000, 10_000] {
+        let (spans, bbox) = page(size);
+        build.bench_with_input(BenchmarkId::from_parameter(size), &spans, |b, spans| {
+            b.iter(|| bench::build(spans, bbox, &[], &opts))
+        });
+    }
+    build.finish();
+
+    let mut flow = c.benchmark_group("flow");
+    for &size in &[100, 1_000, 10_000] {
+        let (spans, bbox) = page(size);
+        let root = bench::build(&spans, bbox, &[], &opts);
+        flow.bench_with_input(BenchmarkId::from_parameter(size), &spans, |b, spans| {
+            b.iter(|| bench::flow(spans, &root, bbox, &opts))
+        });
+    }
+    flow.finish();
+
+    let table_opts = Options::default().detect_tables(true);
+    let mut ruled = c.benchmark_group("ruled");
+    for &rows in &[10, 100, 1_000] {
+        let (spans, lines, bbox) = ruled_table(rows);
+        ruled.bench_with_input(BenchmarkId::from_parameter(rows), &spans, |b, spans| {
+            b.iter(|| bench::build(spans, bbox, &lines, &table_opts))
+        });
+    }
+    ruled.finish();
+}
+
+criterion_group!(benches, layout);
+criterion_main!(benches);
@@ -0,0 +1,90 @@
+//! Experimental: group the text of a document into articles that may span
+//! several columns and pages.
+
+use pdf::{backend::Backend, file::CachedFile, PdfError};
+use serde::{Serialize, Deserialize};
+
+use crate::entry::{Flow, Run, RunType};
+use crate::Options;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Article {
+    /// runs in reading order
+    pub runs: Vec<Run>,
+    /// zero based indices of the pages the article appears on
+    pub pages: Vec<usize>,
+}
+
+/// Extract all pages of `file` and stitch them into articles.
+pub fn run_articles<B: Backend>(file: &CachedFile<B>, opts: &Options) -> Result<Vec<Article>, PdfError> {
+    Ok(articles(crate::run_file(file, opts)?))
+}
+
+/// Split the runs of consecutive pages into articles.
+///
+/// A header starts a new article once the current one has body text.
+/// A paragraph following one that ends mid-sentence is marked as
+/// `ParagraphContinuation`, since the text was broken by a column or page end.
+pub fn articles(flows: Vec<Flow>) -> Vec<Article> {
+    let mut out = vec![];
+    let mut current = Article { runs: vec![], pages: vec![] };
+    let mut has_body = false;
+
+    for (page_nr, flow) in flows.into_iter().enumerate() {
+        for mut run in flow.runs {
+            match run.kind {
+                RunType::Header if has_body => {
+                    out.push(std::mem::replace(&mut current, Article { runs: vec![], pages: vec![] }));
+                    has_body = false;
+                }
+                RunType::Header => {}
+                RunType::Paragraph => {
+                    if let Some(prev) = current.runs.last() {
+                        if prev.kind != RunType::Header && !prev.ends_sentence() {
+                            run.kind = RunType::ParagraphContinuation;
+                        }
+                    }
+                    has_body = true;
+                }
+                _ => has_body = true,
+            }
+            if current.pages.last() != Some(&page_nr) {
+                current.pages.push(page_nr);
+            }
+            current.runs.push(run);
+        }
+    }
+    if current.runs.len() > 0 {
+        out.push(current);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::line;
+
+    fn page(runs: &[(&[&str], RunType)]) -> Flow {
+        let mut flow = Flow::new();
+        for (words, kind) in runs.iter() {
+            flow.add_line(line(words, 0.0, 0.0).words, kind.clone());
+        }
+        flow
+    }
+
+    #[test]
+    fn stitch_across_pages() {
+        let flows = vec![
+            page(&[(&["Title"], RunType::Header), (&["It", "was", "a", "long"], RunType::Paragraph)]),
+            page(&[(&["day."], RunType::Paragraph), (&["Next"], RunType::Header), (&["Text."], RunType::Paragraph)]),
+        ];
+        let articles = articles(flows);
+        assert_eq!(articles.len(), 2);
+        assert_eq!(articles[0].pages, [0, 1]);
+        assert_eq!(articles[0].runs.len(), 3);
+        assert_eq!(articles[0].runs[2].kind, RunType::ParagraphContinuation);
+        assert_eq!(articles[1].pages, [1]);
+        assert_eq!(articles[1].runs[1].kind, RunType::Paragraph);
+    }
+}
@@ -1,52 +1,1202 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use itertools::Itertools;
+use regex::Regex;
 use serde::{Serialize, Deserialize};
 use table::Table;
 
-use crate::util::{Rect, CellContent};
+pub use crate::util::{Rect, CellContent};
+use crate::util::avg;
+use crate::style::{Style, RunStyle};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Word {
     pub text: String,
     pub rect: Rect,
+    /// color of the fill behind the word, if any
+    #[serde(default)]
+    pub highlight: Option<[f32; 3]>,
+    /// indices of the `TextSpan`s this word was built from (see [`run_with_spans`](crate::run_with_spans))
+    #[serde(default)]
+    pub spans: Vec<usize>,
+    /// bold or italic if all of the word is
+    #[serde(default)]
+    pub style: Style,
+    /// the visible chars of the word
+    #[serde(default)]
+    pub chars: Vec<Char>,
+    /// target URI of a link on the word
+    #[serde(default)]
+    pub link: Option<String>,
+    /// a line drawn under or through the word
+    #[serde(default)]
+    pub decoration: Option<Decoration>,
+    /// a wide gap separates the word from the one before, like a tab stop
+    #[serde(default)]
+    pub leader: bool,
+}
+
+/// A line drawn along a [`Word`].
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum Decoration {
+    Underline,
+    Strikethrough,
+}
+
+/// A single glyph of a [`Word`].
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct Char {
+    /// byte offset of the char in [`Word::text`], after normalization
+    pub offset: usize,
+    /// x of the left edge, in the same coordinates as [`Word::rect`] (only set for horizontal text)
+    #[serde(default)]
+    pub pos: f32,
+    #[serde(default)]
+    pub width: f32,
+    #[serde(default)]
+    pub baseline_shift: BaselineShift,
+    /// index of the `TextSpan` (see [`run_with_spans`](crate::run_with_spans)) and of the char in
+    /// its `chars` the char was read from
+    #[serde(default)]
+    pub source: Option<(usize, usize)>,
+    /// `op_nr` of the text operator the char was drawn by and the index of its glyph there.
+    /// Unlike `source`, this still points at the original operator when spans were joined.
+    #[serde(default)]
+    pub glyph: Option<(usize, usize)>,
+}
+
+/// Position of a char relative to the baseline of its line.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+pub enum BaselineShift {
+    #[default]
+    Normal,
+    /// raised and smaller, like footnote references and exponents
+    Superscript,
+    /// lowered and smaller, like chemical formulas
+    Subscript,
 }
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Line {
     pub words: Vec<Word>,
 }
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Run {
     pub lines: Vec<Line>,
     pub kind: RunType,
+    /// heading level from 1 (largest) to 6, 0 for everything that isn't a header
+    ///
+    /// See [`assign_heading_levels`].
+    #[serde(default)]
+    pub level: u8,
+    #[serde(default)]
+    pub style: RunStyle,
+    /// the lines are meant to be kept apart, like in addresses and poems,
+    /// instead of being reflowed. See [`Options::preserve_line_breaks`](crate::Options::preserve_line_breaks).
+    #[serde(default)]
+    pub line_breaks: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+/// The text of a page.
+///
+/// # JSON
+/// [`to_json`](Flow::to_json) writes an object with these fields:
+///
+/// - `version`: the schema version, [`Flow::SCHEMA_VERSION`]
+/// - `runs`: blocks of text in reading order, each with its `kind`, `lines` of `words` and `style`
+/// - `tables`: detected tables and the index of the run they come before
+/// - `warnings`: what went wrong while analyzing the page
+/// - `bbox`: the visible area of the page
+///
+/// New fields may appear in a version without bumping it; readers should ignore unknown fields,
+/// and all fields added after the first version have defaults. The version is bumped when
+/// fields are removed or change their meaning.
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Flow {
+    /// Schema version of the serialized form. Output from before versioning reads as 0.
+    #[serde(default)]
+    pub version: u32,
     pub lines: Vec<Line>,
     pub runs: Vec<Run>,
+    #[serde(default)]
+    pub warnings: Vec<Warning>,
+    #[serde(default)]
+    pub tables: Vec<TableContent>,
+    /// The visible area of the page (its crop box), in the same coordinates as the words.
+    #[serde(default)]
+    pub bbox: Option<Rect>,
+    /// [`to_text`](Flow::to_text) joins the lines of paragraphs, see [`Options::reflow`](crate::Options::reflow).
+    #[serde(default)]
+    pub reflow: bool,
+}
+
+/// A table detected on the page.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TableContent {
+    /// The table comes right before `runs[position]` in reading order.
+    pub position: usize,
+    /// non-empty cells, ordered by row and then column
+    pub cells: Vec<CellContent>,
+    /// number of rows at the top that are headers
+    #[serde(default)]
+    pub header_rows: u32,
+    /// the "Table 3: ..." line above or below the table
+    #[serde(default)]
+    pub caption: Option<String>,
+    /// alignment of each column
+    #[serde(default)]
+    pub alignment: Vec<Align>,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+impl TableContent {
+    /// The cells of each row, from the first row to [`num_rows`](Self::num_rows).
+    /// Rows without any non-empty cell are empty slices, so the n-th item is always row n.
+    pub fn rows(&self) -> impl Iterator<Item=&[CellContent]> {
+        (0 .. self.num_rows()).map(move |row| {
+            let start = self.cells.partition_point(|c| c.row < row);
+            let end = self.cells.partition_point(|c| c.row <= row);
+            &self.cells[start .. end]
+        })
+    }
+    /// Number of rows, taking row spans into account.
+    pub fn num_rows(&self) -> u32 {
+        self.cells.iter().map(|c| c.row + c.rowspan).max().unwrap_or(0)
+    }
+    /// Number of columns, taking column spans into account.
+    pub fn num_cols(&self) -> u32 {
+        self.cells.iter().map(|c| c.col + c.colspan).max().unwrap_or(0)
+    }
+    /// The text of the table as rows of cells.
+    ///
+    /// Every row has `num_cols()` entries. Empty cells are `""`. The text of a spanning cell
+    /// is only put at its top left position, the other positions it covers are `""`.
+    pub fn to_strings(&self) -> Vec<Vec<String>> {
+        let cols = self.num_cols() as usize;
+        self.rows().map(|row| {
+            let mut out = vec![String::new(); cols];
+            for cell in row {
+                out[cell.col as usize] = cell.text.clone();
+            }
+            out
+        }).collect()
+    }
+}
+
+/// A run or a table, in reading order. See [`Flow::blocks`].
+pub enum Block<'a> {
+    Run(&'a Run),
+    Table(&'a TableContent),
 }
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum RunType {
     ParagraphContinuation,
     Paragraph,
     Header,
     Cell,
+    /// An item of a bulleted or numbered list. The marker ("•", "3.", "(ii)") is
+    /// not part of the text. `ordinal` is the number of an ordered item.
+    ListItem { ordinal: Option<u32>, marker: String },
+    /// Text in a monospace font. Each line is a single word that keeps the spacing.
+    Code,
+    /// A footnote at the bottom of the page. `marker` is its number or symbol,
+    /// which is not part of the text.
+    Footnote { marker: Option<String> },
+    /// Where an image is on the page. The run has no lines.
+    Figure { rect: Rect },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// The time budget ran out and the layout of the page is only partially analyzed.
+    TimedOut,
+    /// The page has no text at all. It is empty or consists of images, like a scan.
+    NoText,
 }
 
 impl Flow {
+    /// Version of the JSON schema written by this crate.
+    pub const SCHEMA_VERSION: u32 = 1;
+
     pub fn new() -> Self {
         Flow { 
+            version: Self::SCHEMA_VERSION,
             lines: vec![],
-            runs: vec![]
+            runs: vec![],
+            warnings: vec![],
+            tables: vec![],
+            bbox: None,
+            reflow: false,
         }
     }
+    /// All lines of all runs in reading order.
+    pub fn lines(&self) -> impl Iterator<Item=&Line> {
+        self.runs.iter().flat_map(|r| r.lines.iter())
+    }
+    /// All words in reading order.
+    pub fn words(&self) -> impl Iterator<Item=&Word> {
+        self.lines().flat_map(|l| l.words.iter())
+    }
+    /// Like [`words`](Flow::words), with the kind of run each word is in.
+    pub fn words_with_kind(&self) -> impl Iterator<Item=(&RunType, &Word)> {
+        self.runs.iter().flat_map(|r| r.lines.iter().flat_map(move |l| l.words.iter().map(move |w| (&r.kind, w))))
+    }
+    /// Find `query` in the text, ignoring case.
+    ///
+    /// A match may span several words of a line, any whitespace in `query` matches the space
+    /// between words. Returns the union of the rects of the words of each match.
+    pub fn find(&self, query: &str) -> Vec<Rect> {
+        let query: Vec<char> = query.split_whitespace().join(" ").chars().flat_map(|c| c.to_lowercase()).collect();
+        let mut rects = vec![];
+        for line in self.lines() {
+            for range in line.find(&query) {
+                rects.extend(line.words[range].iter().map(|w| w.rect).reduce(Rect::union));
+            }
+        }
+        rects
+    }
+    /// Run `re` over the text of the flow and return each match with the rects of the words it touches.
+    ///
+    /// The text is every line in reading order followed by a newline, with single spaces between
+    /// the words, see [`search_text`](Flow::search_text). The ranges are byte offsets into it.
+    ///
+    /// This is not behind a feature, since `regex` is needed anyway for the font name
+    /// patterns in [`StyleClassifier`](crate::style::StyleClassifier).
+    pub fn search_regex(&self, re: &Regex) -> Vec<(Range<usize>, Vec<Rect>)> {
+        let (text, words) = self.search_text();
+        re.find_iter(&text).map(|m| {
+            let range = m.range();
+            let rects = words.iter()
+                .filter(|(r, _)| r.start < range.end && range.start < r.end.max(r.start + 1))
+                .map(|&(_, w)| w.rect)
+                .collect();
+            (range, rects)
+        }).collect()
+    }
+    /// Where in the content stream the matches of `query` are drawn, to blank them out.
+    ///
+    /// `query` matches like in [`find`](Flow::find). Returns the `op_nr` of the text operator and
+    /// the range of its glyphs for each piece of a match, in reading order, from [`Char::glyph`].
+    pub fn redaction_targets(&self, query: &str) -> Vec<(usize, Range<usize>)> {
+        let pattern = query.split_whitespace().map(regex::escape).join(r"\s+");
+        let re = match Regex::new(&format!("(?i){}", pattern)) {
+            Ok(re) if pattern.len() > 0 => re,
+            _ => return vec![]
+        };
+        let (text, words) = self.search_text();
+        let mut targets: Vec<(usize, Range<usize>)> = vec![];
+        for m in re.find_iter(&text) {
+            for (range, word) in words.iter() {
+                if range.end <= m.start() || range.start >= m.end() {
+                    continue;
+                }
+                for (i, c) in word.chars.iter().enumerate() {
+                    let start = range.start + c.offset;
+                    let end = range.start + word.chars.get(i + 1).map_or(word.text.len(), |n| n.offset);
+                    if end <= m.start() || start >= m.end() {
+                        continue;
+                    }
+                    let (op_nr, glyph) = match c.glyph {
+                        Some(glyph) => glyph,
+                        None => continue
+                    };
+                    match targets.last_mut() {
+                        Some((op, glyphs)) if *op == op_nr && glyphs.end == glyph => glyphs.end += 1,
+                        _ => targets.push((op_nr, glyph .. glyph + 1)),
+                    }
+                }
+            }
+        }
+        targets
+    }
+    /// The text [`search_regex`](Flow::search_regex) searches, and the byte range of each word in it.
+    pub fn search_text(&self) -> (String, Vec<(Range<usize>, &Word)>) {
+        let mut text = String::new();
+        let mut words = vec![];
+        for line in self.lines() {
+            for (i, word) in line.words.iter().enumerate() {
+                if i > 0 {
+                    text.push(' ');
+                }
+                let start = text.len();
+                text.push_str(&word.text);
+                words.push((start .. text.len(), word));
+            }
+            text.push('\n');
+        }
+        (text, words)
+    }
     pub fn add_line(&mut self, words: Vec<Word>, kind: RunType) {
         if words.len() > 0 {
             self.runs.push(Run {
                 lines: vec![Line { words }], 
-                kind
+                kind,
+                level: 0,
+                style: RunStyle::default(),
+                line_breaks: false,
             });
         }
     }
-    pub fn add_table(&mut self, table: Table<CellContent>) {
-        
+    pub fn add_table(&mut self, table: Table<CellContent>, header_rows: u32, alignment: Vec<Align>) {
+        let mut cells: Vec<CellContent> = table.values().map(|v| v.value.clone()).collect();
+        if cells.len() == 0 {
+            return;
+        }
+        cells.sort_by_key(|c| (c.row, c.col));
+        self.tables.push(TableContent {
+            position: self.runs.len(),
+            cells,
+            header_rows,
+            caption: None,
+            alignment,
+        });
+    }
+
+    /// Insert `run` at `runs[idx]`, keeping the tables in place.
+    fn insert_run(&mut self, idx: usize, run: Run) {
+        for table in self.tables.iter_mut() {
+            if table.position > idx {
+                table.position += 1;
+            }
+        }
+        self.runs.insert(idx, run);
+    }
+
+    /// Add a [`RunType::Figure`] for each image in `images`.
+    ///
+    /// A figure goes before the first run that starts below its top and overlaps it horizontally,
+    /// or at the end if there is none.
+    pub(crate) fn add_figures(&mut self, images: &[Rect]) {
+        for &rect in images {
+            let below = |run: &Run| {
+                let mut words = run.lines.iter().flat_map(|l| l.words.iter());
+                match words.next() {
+                    Some(first) => first.rect.y >= rect.y && words.chain(Some(first))
+                        .any(|w| w.rect.x < rect.x + rect.w && rect.x < w.rect.x + w.rect.w),
+                    None => false
+                }
+            };
+            let idx = self.runs.iter().position(below).unwrap_or(self.runs.len());
+            self.insert_run(idx, Run { lines: vec![], kind: RunType::Figure { rect }, level: 0, style: RunStyle::default(), line_breaks: false });
+        }
+    }
+
+    /// Remove `runs[idx]`, keeping the tables in place.
+    fn remove_run(&mut self, idx: usize) -> Run {
+        for table in self.tables.iter_mut() {
+            if table.position > idx {
+                table.position -= 1;
+            }
+        }
+        self.runs.remove(idx)
+    }
+
+    /// Move single line runs right above or below a table that start with one of
+    /// `prefixes` into the caption of that table. The line above wins.
+    pub(crate) fn attach_captions(&mut self, prefixes: &[String]) {
+        let is_caption = |run: &Run| {
+            if run.lines.len() != 1 || run.lines[0].words.len() > 40 {
+                return false;
+            }
+            let text = run.lines[0].text();
+            prefixes.iter().any(|p| text.starts_with(p.as_str()))
+        };
+        for t in 0 .. self.tables.len() {
+            let pos = self.tables[t].position;
+            let idx = if pos > 0 && is_caption(&self.runs[pos - 1]) {
+                pos - 1
+            } else if pos < self.runs.len() && is_caption(&self.runs[pos]) {
+                pos
+            } else {
+                continue;
+            };
+            let run = self.remove_run(idx);
+            self.tables[t].caption = Some(run.lines[0].text());
+        }
+    }
+
+    /// Mark paragraphs that continue the one before them as [`RunType::ParagraphContinuation`].
+    ///
+    /// That is the case when the paragraph before ends mid-sentence and has the same style,
+    /// because a column end cut it in two. Must run after the run styles are set.
+    pub(crate) fn mark_continuations(&mut self) {
+        for i in 1 .. self.runs.len() {
+            // a table in between ends the paragraph
+            if self.tables.iter().any(|t| t.position == i) {
+                continue;
+            }
+            let (before, after) = self.runs.split_at_mut(i);
+            if before[i - 1].continued_by(&after[0]) {
+                after[0].kind = RunType::ParagraphContinuation;
+            }
+        }
+    }
+
+    /// Put drop caps (a large initial letter spanning several lines) in front of the paragraph they start.
+    ///
+    /// A drop cap ends up as a run of its own with a single letter, right before the paragraph,
+    /// left of its first line and at least twice as high.
+    pub(crate) fn fold_drop_caps(&mut self) {
+        let mut i = 0;
+        while i + 1 < self.runs.len() {
+            let cap = match self.runs[i].lines[..] {
+                [ref line] if line.words.len() == 1 => &line.words[0],
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let next = &self.runs[i + 1];
+            let is_letter = {
+                let mut chars = cap.text.chars();
+                matches!((chars.next(), chars.next()), (Some(c), None) if c.is_alphabetic())
+            };
+            let next_is_table = self.tables.iter().any(|t| t.position == i + 1);
+            let first = next.lines.first().and_then(|l| l.words.first());
+            let line_height = next.lines.first().and_then(|l| avg(l.words.iter().map(|w| w.rect.h)));
+            let is_drop_cap = match (first, line_height) {
+                (Some(first), Some(h)) => is_letter && !next_is_table
+                    && matches!(self.runs[i].kind, RunType::Paragraph | RunType::Header)
+                    && next.kind == RunType::Paragraph
+                    && cap.rect.h >= 2.0 * h
+                    // left of the first word, but not far
+                    && cap.rect.x + cap.rect.w <= first.rect.x + 0.5 * h
+                    && first.rect.x - (cap.rect.x + cap.rect.w) < 2.0 * h
+                    // tops aligned
+                    && (cap.rect.y - first.rect.y).abs() < h,
+                _ => false
+            };
+            if is_drop_cap {
+                let cap = self.remove_run(i).lines.remove(0).words.remove(0);
+                self.runs[i].lines[0].words[0].prepend(cap);
+            }
+            i += 1;
+        }
+    }
+
+    /// Turn paragraphs that start with a bullet or an ordinal ("1.", "a)", "(iv)") into list items.
+    ///
+    /// A marker that ended up as a run of its own (because it is far left of the text)
+    /// is merged into the run that follows it.
+    pub(crate) fn detect_lists(&mut self) {
+        let mut i = 0;
+        while i < self.runs.len() {
+            if self.runs[i].kind != RunType::Paragraph {
+                i += 1;
+                continue;
+            }
+            let lone_marker = match self.runs[i].lines[..] {
+                [ref line] if line.words.len() == 1 => list_marker(&line.words[0].text).is_some(),
+                _ => false
+            };
+            let next_is_table = self.tables.iter().any(|t| t.position == i + 1);
+            if lone_marker && i + 1 < self.runs.len() && !next_is_table
+                && self.runs[i + 1].kind == RunType::Paragraph && self.runs[i + 1].lines.len() > 0
+            {
+                let marker = self.remove_run(i);
+                let word = marker.lines.into_iter().next().unwrap().words.into_iter().next().unwrap();
+                self.runs[i].lines[0].words.insert(0, word);
+            }
+
+            let run = &mut self.runs[i];
+            if let Some(first) = run.lines.first_mut() {
+                if let Some(word) = first.words.first_mut() {
+                    if let Some((marker, ordinal)) = split_marker(&word.text) {
+                        if word.text.len() > marker.len() {
+                            word.drop_prefix(marker.len());
+                        } else if first.words.len() > 1 {
+                            first.words.remove(0);
+                        } else {
+                            i += 1;
+                            continue;
+                        }
+                        run.kind = RunType::ListItem { ordinal, marker };
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+
+    /// The text of all tables, see [`TableContent::to_strings`].
+    pub fn tables(&self) -> Vec<Vec<Vec<String>>> {
+        self.tables.iter().map(|t| t.to_strings()).collect()
+    }
+
+    /// Runs and tables in reading order.
+    pub fn blocks(&self) -> Vec<Block> {
+        let mut out = Vec::with_capacity(self.runs.len() + self.tables.len());
+        let mut tables = self.tables.iter().peekable();
+        for (i, run) in self.runs.iter().enumerate() {
+            while let Some(table) = tables.next_if(|t| t.position <= i) {
+                out.push(Block::Table(table));
+            }
+            out.push(Block::Run(run));
+        }
+        out.extend(tables.map(Block::Table));
+        out
+    }
+
+    /// Plain text in reading order.
+    ///
+    /// Lines of a run are separated by a newline and runs by a blank line.
+    /// Headers are always followed by a blank line.
+    /// Table rows become lines with the cells separated by tabs, and so are [leaders](Word::leader).
+    ///
+    /// With [`reflow`](Flow::reflow), the lines of a run are joined by spaces instead, except in code
+    /// and runs with [`line_breaks`](Run::line_breaks), and a
+    /// [`ParagraphContinuation`](RunType::ParagraphContinuation) goes on the line of the run before it.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let mut blank = true;
+        for block in self.blocks() {
+            // figures have no text
+            if matches!(block, Block::Run(run) if run.lines.len() == 0) {
+                continue;
+            }
+            let continues = self.reflow && !blank && out.ends_with('\n')
+                && matches!(block, Block::Run(Run { kind: RunType::ParagraphContinuation, .. }));
+            if continues {
+                out.pop();
+                out.push(' ');
+            } else if !blank {
+                out.push('\n');
+            }
+            match block {
+                Block::Run(run) => {
+                    for (i, line) in run.lines.iter().enumerate() {
+                        match (i, &run.kind) {
+                            (0, RunType::ListItem { marker, .. }) |
+                            (0, RunType::Footnote { marker: Some(marker) }) => {
+                                out.push_str(marker);
+                                out.push(' ');
+                            }
+                            _ => {}
+                        }
+                        out.push_str(&line.text());
+                        let joined = self.reflow && !run.line_breaks && run.kind != RunType::Code;
+                        out.push(if joined && i + 1 < run.lines.len() { ' ' } else { '\n' });
+                    }
+                    blank = run.kind == RunType::Header;
+                }
+                Block::Table(table) => {
+                    // one line per row, cells separated by tabs
+                    for row in table.rows() {
+                        out.push_str(&row.iter().map(|c| &c.text).join("\t"));
+                        out.push('\n');
+                    }
+                    blank = false;
+                }
+            }
+            if blank {
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// [`assign_heading_levels`] for a single page.
+    pub fn assign_heading_levels(&mut self) {
+        assign_heading_levels(std::slice::from_mut(self));
+    }
+
+    /// Apply [`repair_mojibake`](crate::repair_mojibake) to every word.
+    pub fn repair_mojibake(&mut self) {
+        for run in self.runs.iter_mut() {
+            for line in run.lines.iter_mut() {
+                for word in line.words.iter_mut() {
+                    word.text = crate::text::repair_mojibake(&word.text);
+                }
+            }
+        }
+    }
+
+    /// Serialize to JSON, see [the schema](Flow#json).
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+    /// Parse JSON written by [`to_json`](Flow::to_json).
+    ///
+    /// Fails on flows from a newer version of the schema than this crate knows.
+    pub fn from_json(json: &str) -> Result<Flow, serde_json::Error> {
+        use serde::de::Error;
+
+        let flow: Flow = serde_json::from_str(json)?;
+        if flow.version > Self::SCHEMA_VERSION {
+            return Err(serde_json::Error::custom(format!(
+                "unsupported schema version {} (expected at most {})", flow.version, Self::SCHEMA_VERSION
+            )));
+        }
+        Ok(flow)
+    }
+
+    /// All runs of `flows` in order, with each [`RunType::ParagraphContinuation`] appended
+    /// to the run before it, even across pages. Tables are left out.
+    pub fn merge_continuations(flows: &[Flow]) -> Vec<MergedRun> {
+        let mut merged: Vec<MergedRun> = vec![];
+        for (page_nr, flow) in flows.iter().enumerate() {
+            for run in flow.runs.iter() {
+                match merged.last_mut() {
+                    Some(last) if run.kind == RunType::ParagraphContinuation => {
+                        last.run.lines.extend(run.lines.iter().cloned());
+                        if last.pages.last() != Some(&page_nr) {
+                            last.pages.push(page_nr);
+                        }
+                    }
+                    _ => merged.push(MergedRun { run: run.clone(), pages: vec![page_nr] }),
+                }
+            }
+        }
+        merged
+    }
+
+    /// Returns a flow with only the first `n_lines` lines.
+    ///
+    /// The run containing the last line is cut short. Tables before and right after
+    /// the kept runs stay.
+    pub fn head(&self, n_lines: usize) -> Flow {
+        let mut remaining = n_lines;
+        let mut runs = vec![];
+        for run in self.runs.iter() {
+            if remaining == 0 {
+                break;
+            }
+            let lines: Vec<Line> = run.lines.iter().take(remaining).cloned().collect();
+            remaining -= lines.len();
+            runs.push(Run { lines, kind: run.kind.clone(), level: run.level, style: run.style.clone(), line_breaks: run.line_breaks });
+        }
+        let tables = self.tables.iter().filter(|t| t.position <= runs.len()).cloned().collect();
+        Flow {
+            version: self.version,
+            lines: vec![],
+            runs,
+            warnings: self.warnings.clone(),
+            tables,
+            bbox: self.bbox,
+            reflow: self.reflow,
+        }
+    }
+}
+
+impl Word {
+    /// Put `other` in front of this word, without a space.
+    pub(crate) fn prepend(&mut self, other: Word) {
+        let len = other.text.len();
+        for c in self.chars.iter_mut() {
+            c.offset += len;
+        }
+        self.chars.splice(0..0, other.chars);
+        self.text.insert_str(0, &other.text);
+        self.spans.splice(0..0, other.spans);
+        self.rect = other.rect.union(self.rect);
+    }
+    /// Remove the first `len` bytes of the text.
+    pub(crate) fn drop_prefix(&mut self, len: usize) {
+        self.text.drain(..len);
+        self.chars.retain(|c| c.offset >= len);
+        for c in self.chars.iter_mut() {
+            c.offset -= len;
+        }
+    }
+}
+
+impl Line {
+    /// the words separated by spaces, or by a tab before a [`leader`](Word::leader)
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for (i, word) in self.words.iter().enumerate() {
+            if i > 0 {
+                text.push(if word.leader { '\t' } else { ' ' });
+            }
+            text.push_str(&word.text);
+        }
+        text
+    }
+    /// The ranges of words that contain the (lowercase, single spaced) `query`.
+    fn find(&self, query: &[char]) -> Vec<Range<usize>> {
+        // lowercase text with single spaces between the words, and the word each char belongs to
+        let mut chars: Vec<(char, usize)> = vec![];
+        for (i, word) in self.words.iter().enumerate() {
+            if i > 0 {
+                chars.push((' ', i));
+            }
+            for c in word.text.chars().flat_map(|c| c.to_lowercase()) {
+                chars.push((if c.is_whitespace() { ' ' } else { c }, i));
+            }
+        }
+        if query.len() == 0 || query.len() > chars.len() {
+            return vec![];
+        }
+        let mut found = vec![];
+        for start in 0 ..= chars.len() - query.len() {
+            let window = &chars[start .. start + query.len()];
+            if window.iter().map(|&(c, _)| c).eq(query.iter().cloned()) {
+                // a leading space belongs to the word after it
+                let first = window.iter().find(|&&(c, _)| c != ' ').map_or(window[0].1, |&(_, i)| i);
+                let last = window.last().unwrap().1;
+                found.push(first .. last + 1);
+            }
+        }
+        found
+    }
+}
+
+impl Run {
+    /// average height of the words
+    fn height(&self) -> Option<f32> {
+        avg(self.lines.iter().flat_map(|l| l.words.iter()).map(|w| w.rect.h))
+    }
+    /// `next` is a paragraph in the same style and this one stops mid-sentence.
+    fn continued_by(&self, next: &Run) -> bool {
+        matches!(self.kind, RunType::Paragraph | RunType::ParagraphContinuation)
+            && next.kind == RunType::Paragraph
+            && !self.ends_sentence()
+            && self.style.font == next.style.font
+            && self.style.bold == next.style.bold
+            && self.style.italic == next.style.italic
+            && (self.style.font_size - next.style.font_size).abs() <= 0.1 * self.style.font_size
+    }
+    /// The first line starts further right than the others, like the start of a paragraph.
+    /// False for a single line.
+    fn first_line_indented(&self) -> bool {
+        let start = |line: &Line| line.words.first().map(|w| w.rect.x);
+        let first = match self.lines.first().and_then(start) {
+            Some(x) => x,
+            None => return false
+        };
+        let rest = self.lines[1..].iter().filter_map(start).reduce(f32::min);
+        match (rest, self.height()) {
+            (Some(rest), Some(h)) => first > rest + h,
+            _ => false
+        }
+    }
+    /// The last word ends with sentence punctuation, or there is no text.
+    pub(crate) fn ends_sentence(&self) -> bool {
+        self.lines.last()
+            .and_then(|line| line.words.last())
+            .and_then(|word| word.text.trim_end().chars().last())
+            .map(|c| matches!(c, '.' | '!' | '?' | ':' | '"' | '”' | '»'))
+            .unwrap_or(true)
+    }
+}
+
+/// Mark the first paragraph of a page as [`RunType::ParagraphContinuation`] when it continues
+/// the last paragraph of the page before.
+///
+/// Besides the checks within a page, the first line of the paragraph must not be indented.
+/// Headers and footers have to be gone already, or they will sit between the two.
+pub fn mark_page_continuations(flows: &mut [Flow]) {
+    for i in 1 .. flows.len() {
+        let (before, after) = flows.split_at_mut(i);
+        let (prev, next) = (&before[i - 1], &mut after[0]);
+        // tables at the end or start of the pages end the paragraph
+        if prev.tables.iter().any(|t| t.position == prev.runs.len()) || next.tables.iter().any(|t| t.position == 0) {
+            continue;
+        }
+        let (last, first) = match (prev.runs.last(), next.runs.first_mut()) {
+            (Some(last), Some(first)) => (last, first),
+            _ => continue
+        };
+        if last.continued_by(first) && !first.first_line_indented() {
+            first.kind = RunType::ParagraphContinuation;
+        }
+    }
+}
+
+/// A run with the continuations that follow it appended, from [`Flow::merge_continuations`].
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MergedRun {
+    pub run: Run,
+    /// zero based indices of the flows the lines come from
+    pub pages: Vec<usize>,
+}
+
+/// Set the [`level`](Run::level) of all headers in `flows`.
+///
+/// The text heights of the headers are clustered, with a new cluster starting whenever
+/// the height drops by more than 10%. The largest cluster gets level 1, the next level 2
+/// and so on. Anything below the sixth cluster is level 6.
+pub fn assign_heading_levels(flows: &mut [Flow]) {
+    let mut heights: Vec<f32> = flows.iter()
+        .flat_map(|f| f.runs.iter())
+        .filter(|r| r.kind == RunType::Header)
+        .filter_map(|r| r.height())
+        .collect();
+    heights.sort_unstable_by(|a, b| b.total_cmp(a));
+
+    // smallest height in each cluster, largest cluster first
+    let mut cluster_mins: Vec<f32> = vec![];
+    for h in heights {
+        match cluster_mins.last_mut() {
+            Some(min) if h >= 0.9 * *min => *min = h,
+            _ => cluster_mins.push(h),
+        }
+    }
+
+    for run in flows.iter_mut().flat_map(|f| f.runs.iter_mut()) {
+        run.level = match (&run.kind, run.height()) {
+            (RunType::Header, Some(h)) => {
+                let above = cluster_mins.iter().filter(|&&min| min > h).count();
+                (1 + above).min(6) as u8
+            }
+            (RunType::Header, None) => 1,
+            _ => 0,
+        };
+    }
+}
+
+const BULLETS: &[char] = &['•', '◦', '▪', '▫', '‣', '∙', '●', '○', '■', '□', '-', '–', '*'];
+
+/// If `text` is a list marker, its ordinal (`None` for bullets).
+fn list_marker(text: &str) -> Option<Option<u32>> {
+    let mut chars = text.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if BULLETS.contains(&c) {
+            return Some(None);
+        }
+    }
+    // "A." is more likely an initial than a list item, so letters need a parenthesis
+    let (inner, paren) = if let Some(t) = text.strip_prefix('(') {
+        (t.strip_suffix(')')?, true)
+    } else if let Some(t) = text.strip_suffix(')') {
+        (t, true)
+    } else {
+        (text.strip_suffix('.')?, false)
+    };
+    if inner.len() > 0 && inner.chars().all(|c| c.is_ascii_digit()) {
+        // longer numbers are years ("2024. was ...") rather than list items
+        if inner.len() > 3 {
+            return None;
+        }
+        return inner.parse().ok().map(Some);
+    }
+    let mut chars = inner.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if paren && c.is_ascii_alphabetic() => Some(Some(c.to_ascii_lowercase() as u32 - 'a' as u32 + 1)),
+        (Some(_), Some(_)) => roman(inner).map(Some),
+        _ => None
+    }
+}
+
+/// The value of a lowercase roman numeral.
+fn roman(text: &str) -> Option<u32> {
+    let mut value = 0;
+    let mut last = 0;
+    for c in text.chars().rev() {
+        let v = match c {
+            'i' => 1, 'v' => 5, 'x' => 10,
+            _ => return None
+        };
+        if v < last {
+            value -= v;
+        } else {
+            value += v;
+            last = v;
+        }
+    }
+    Some(value)
+}
+
+/// Split a list marker off the start of a word: either the whole word is a marker,
+/// or it starts with a bullet glued to the text ("•Item").
+fn split_marker(word: &str) -> Option<(String, Option<u32>)> {
+    if let Some(ordinal) = list_marker(word) {
+        return Some((word.to_string(), ordinal));
+    }
+    let c = word.chars().next()?;
+    // '-' and '*' glued to a word are more likely a sign or a footnote
+    if BULLETS.contains(&c) && !matches!(c, '-' | '–' | '*') {
+        return Some((c.to_string(), None));
+    }
+    None
+}
+
+/// Text of a line with case, digits (page numbers) and extra whitespace removed,
+/// for comparing running headers across pages.
+fn normalized_text(line: &Line) -> String {
+    line.text()
+        .chars()
+        .filter(|c| !c.is_numeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect::<String>()
+        .split_whitespace()
+        .join(" ")
+}
+
+/// Remove running headers and footers: single line runs among the first or last
+/// two runs of a page whose text (ignoring page numbers) is found in the same place
+/// on the majority of pages.
+///
+/// Unlike the per page heuristic ([`Options::strip_header_footer`](crate::Options::strip_header_footer)),
+/// text that only shows up once, like a banner on the first page, is kept.
+pub fn strip_repeated_header_footer(flows: &mut [Flow]) {
+    // index of the candidate runs, from the top (true) or bottom (false)
+    let candidates = |flow: &Flow| -> Vec<(bool, usize)> {
+        let n = flow.runs.len();
+        let top = (0 .. n.min(2)).map(|i| (true, i));
+        let bottom = (n.saturating_sub(2) .. n).rev().map(|i| (false, i));
+        top.chain(bottom)
+            .filter(|&(_, i)| flow.runs[i].lines.len() == 1)
+            .unique_by(|&(_, i)| i)
+            .collect()
+    };
+
+    let mut counts: HashMap<(bool, String), usize> = HashMap::new();
+    for flow in flows.iter() {
+        let texts: HashSet<(bool, String)> = candidates(flow).into_iter()
+            .map(|(top, i)| (top, normalized_text(&flow.runs[i].lines[0])))
+            .filter(|(_, text)| text.len() > 0)
+            .collect();
+        for key in texts {
+            *counts.entry(key).or_default() += 1;
+        }
+    }
+
+    let pages = flows.len();
+    for flow in flows.iter_mut() {
+        let mut remove: Vec<usize> = candidates(flow).into_iter()
+            .filter(|&(top, i)| {
+                let key = (top, normalized_text(&flow.runs[i].lines[0]));
+                counts.get(&key).map(|&n| n >= 2 && 2 * n > pages).unwrap_or(false)
+            })
+            .map(|(_, i)| i)
+            .collect();
+        remove.sort_unstable();
+        for &i in remove.iter().rev() {
+            flow.remove_run(i);
+        }
+    }
+}
+
+/// Text at the start of a document: the title and whatever follows it
+/// up to the next heading (usually authors and abstract).
+///
+/// Only the first page is considered. If it has no text (e.g. a cover image),
+/// the result is empty.
+pub fn leading_text(pages: &[Flow]) -> String {
+    let mut out = String::new();
+    let first = match pages.first() {
+        Some(flow) => flow,
+        None => return out
+    };
+
+    let mut past_title = false;
+    for run in first.runs.iter() {
+        if run.kind == RunType::Header {
+            if past_title {
+                break;
+            }
+        } else {
+            past_title = true;
+        }
+        if out.len() > 0 {
+            out.push_str("\n\n");
+        }
+        for (i, line) in run.lines.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str(&line.text());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::line;
+
+    fn run(lines: Vec<Line>) -> Run {
+        Run { lines, kind: RunType::Paragraph, level: 0, style: RunStyle::default(), line_breaks: false }
+    }
+    fn table(position: usize, cells: &[(u32, u32, &str)]) -> TableContent {
+        let cells = cells.iter().map(|&(row, col, text)| CellContent {
+            text: text.into(),
+            rect: Rect { x: 0.0, y: 0.0, w: 0.0, h: 0.0 },
+            row,
+            col,
+            rowspan: 1,
+            colspan: 1,
+        }).collect();
+        TableContent { position, cells, header_rows: 0, caption: None, alignment: vec![] }
+    }
+
+    #[test]
+    fn head_keeps_trailing_table() {
+        let mut flow = Flow::new();
+        flow.runs.push(run(vec![line(&["one"], 0.0, 0.0), line(&["two"], 0.0, 20.0)]));
+        flow.tables.push(table(1, &[(0, 0, "a")]));
+
+        let head = flow.head(5);
+        assert_eq!(head.runs.len(), 1);
+        assert_eq!(head.tables.len(), 1);
+
+        let head = flow.head(1);
+        assert_eq!(head.runs[0].lines.len(), 1);
+    }
+
+    #[test]
+    fn search_regex_across_lines() {
+        let mut flow = Flow::new();
+        flow.runs.push(run(vec![line(&["total", "12"], 0.0, 0.0), line(&["34", "EUR"], 0.0, 20.0)]));
+        let matches = flow.search_regex(&Regex::new(r"\d+\s\d+").unwrap());
+        assert_eq!(matches.len(), 1);
+        let (range, rects) = &matches[0];
+        assert_eq!(*range, 6 .. 11);
+        assert_eq!(rects.len(), 2);
+        assert_eq!(rects[1].y, 20.0);
+    }
+
+    #[test]
+    fn table_rows_keep_empty_rows() {
+        let table = table(0, &[(0, 0, "a"), (0, 1, "b"), (2, 1, "c")]);
+        assert_eq!(table.rows().map(|row| row.len()).collect::<Vec<_>>(), [2, 0, 1]);
+        assert_eq!(table.to_strings(), [vec!["a", "b"], vec!["", ""], vec!["", "c"]]);
+    }
+
+    #[test]
+    fn bullets() {
+        assert_eq!(list_marker("•"), Some(None));
+        assert_eq!(list_marker("–"), Some(None));
+        assert_eq!(split_marker("•Item"), Some(("•".into(), None)));
+        assert_eq!(split_marker("-5"), None);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(list_marker("1."), Some(Some(1)));
+        assert_eq!(list_marker("12)"), Some(Some(12)));
+        assert_eq!(list_marker("(3)"), Some(Some(3)));
+        assert_eq!(list_marker("2024."), None);
+        assert_eq!(list_marker("1.5"), None);
+    }
+
+    #[test]
+    fn letters() {
+        assert_eq!(list_marker("(a)"), Some(Some(1)));
+        assert_eq!(list_marker("c)"), Some(Some(3)));
+        // an initial, not a list item
+        assert_eq!(list_marker("A."), None);
+    }
+
+    #[test]
+    fn roman_numerals() {
+        assert_eq!(list_marker("iv."), Some(Some(4)));
+        assert_eq!(list_marker("(xii)"), Some(Some(12)));
+        assert_eq!(roman("ix"), Some(9));
+        assert_eq!(roman("ok"), None);
+        // "I think" starts with a pronoun, not a numeral
+        assert_eq!(list_marker("I"), None);
+        assert_eq!(list_marker("it."), None);
+    }
+
+    #[test]
+    fn figures_go_before_the_text_below() {
+        let mut flow = Flow::new();
+        flow.runs.push(run(vec![line(&["above"], 0.0, 0.0)]));
+        flow.runs.push(run(vec![line(&["below"], 0.0, 100.0)]));
+        let text = flow.to_text();
+
+        let figure = Rect { x: 0.0, y: 50.0, w: 100.0, h: 40.0 };
+        let margin = Rect { x: 500.0, y: 50.0, w: 50.0, h: 40.0 };
+        flow.add_figures(&[figure, margin]);
+        let kinds: Vec<_> = flow.runs.iter().map(|r| r.kind.clone()).collect();
+        assert_eq!(kinds, [
+            RunType::Paragraph,
+            RunType::Figure { rect: figure },
+            RunType::Paragraph,
+            RunType::Figure { rect: margin },
+        ]);
+        assert_eq!(flow.to_text(), text);
+    }
+
+    #[test]
+    fn reflow_keeps_line_breaks() {
+        let mut flow = Flow::new();
+        flow.reflow = true;
+        flow.runs.push(run(vec![line(&["one"], 0.0, 0.0), line(&["two"], 0.0, 20.0)]));
+        assert_eq!(flow.to_text(), "one two\n");
+        flow.runs[0].line_breaks = true;
+        assert_eq!(flow.to_text(), "one\ntwo\n");
+    }
+
+    #[test]
+    fn paragraphs_cut_by_a_column_end() {
+        let mut flow = Flow::new();
+        flow.runs.push(run(vec![line(&["the", "end", "of"], 0.0, 0.0)]));
+        flow.runs.push(run(vec![line(&["the", "sentence."], 300.0, 0.0)]));
+        flow.runs.push(run(vec![line(&["next", "one"], 300.0, 20.0)]));
+        flow.runs.push(run(vec![line(&["after", "a", "table"], 300.0, 80.0)]));
+        flow.tables.push(table(3, &[(0, 0, "a")]));
+        flow.mark_continuations();
+        let kinds: Vec<_> = flow.runs.iter().map(|r| r.kind.clone()).collect();
+        assert_eq!(kinds, [RunType::Paragraph, RunType::ParagraphContinuation, RunType::Paragraph, RunType::Paragraph]);
+    }
+
+    #[test]
+    fn paragraphs_across_pages() {
+        let page = |runs: Vec<Run>| {
+            let mut flow = Flow::new();
+            flow.runs = runs;
+            flow
+        };
+        let mut flows = vec![
+            page(vec![run(vec![line(&["continues", "on"], 0.0, 700.0)])]),
+            page(vec![run(vec![line(&["the", "next", "page", "and"], 0.0, 50.0)])]),
+            // indented, so a new paragraph even though the one before does not end
+            page(vec![run(vec![line(&["A", "new"], 20.0, 50.0), line(&["paragraph."], 0.0, 62.0)])]),
+        ];
+        mark_page_continuations(&mut flows);
+        assert_eq!(flows[1].runs[0].kind, RunType::ParagraphContinuation);
+        assert_eq!(flows[2].runs[0].kind, RunType::Paragraph);
+
+        let merged = Flow::merge_continuations(&flows);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].pages, [0, 1]);
+        assert_eq!(merged[0].run.lines.len(), 2);
+    }
+
+    #[test]
+    fn drop_caps() {
+        let mut cap = line(&["O"], 0.0, 0.0);
+        cap.words[0].rect.w = 15.0;
+        cap.words[0].rect.h = 30.0;
+        let mut flow = Flow::new();
+        flow.runs.push(run(vec![cap]));
+        flow.runs.push(run(vec![line(&["nce", "upon"], 20.0, 0.0), line(&["a", "time"], 20.0, 12.0)]));
+        flow.fold_drop_caps();
+        assert_eq!(flow.runs.len(), 1);
+        let first = &flow.runs[0].lines[0].words[0];
+        assert_eq!(first.text, "Once");
+        assert_eq!(first.chars.iter().map(|c| c.offset).collect::<Vec<_>>(), [0, 1, 2, 3]);
+
+        // a letter of the normal size is left alone
+        let mut flow = Flow::new();
+        flow.runs.push(run(vec![line(&["A"], 0.0, 0.0)]));
+        flow.runs.push(run(vec![line(&["list"], 20.0, 0.0)]));
+        flow.fold_drop_caps();
+        assert_eq!(flow.runs.len(), 2);
+    }
+
+    #[test]
+    fn json_schema_version() {
+        let mut flow = Flow::new();
+        flow.runs.push(run(vec![line(&["Hello", "World"], 0.0, 0.0)]));
+        let json = flow.to_json().unwrap();
+        let read = Flow::from_json(&json).unwrap();
+        assert_eq!(read.version, Flow::SCHEMA_VERSION);
+        assert_eq!(read.to_json().unwrap(), json);
+
+        // written before the schema had a version
+        assert_eq!(Flow::from_json(r#"{"lines": [], "runs": []}"#).unwrap().version, 0);
+
+        let newer = format!(r#"{{"version": {}, "lines": [], "runs": []}}"#, Flow::SCHEMA_VERSION + 1);
+        assert!(Flow::from_json(&newer).is_err());
     }
 }
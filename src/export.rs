@@ -0,0 +1,642 @@
+//! Conversion of a [`Flow`] into other text formats.
+
+use itertools::Itertools;
+
+use crate::entry::{Align, BaselineShift, Block, Decoration, Flow, Line, Run, RunType, TableContent, Word};
+use crate::util::{avg, is_number};
+
+fn line_text(line: &Line, escape: impl Fn(&str) -> String) -> String {
+    line.words.iter().map(|w| escape(w.text.as_str())).join(" ")
+}
+
+/// How to mark up styled text in an output format.
+struct Markup {
+    escape: fn(&str) -> String,
+    /// wraps italic text
+    em: (&'static str, &'static str),
+    /// marks up escaped superscript text
+    sup: fn(&str) -> String,
+    /// marks up escaped subscript text
+    sub: fn(&str) -> String,
+    /// marks up marked up text and the URI it links to
+    link: fn(&str, &str) -> String,
+    /// wraps underlined words
+    underline: (&'static str, &'static str),
+    /// wraps struck out words
+    strike: (&'static str, &'static str),
+}
+
+const MARKDOWN: Markup = Markup {
+    escape: escape_markdown,
+    em: ("_", "_"),
+    // superscript numbers are footnote references
+    sup: |s| if is_number(s) { format!("[^{}]", s) } else { s.to_string() },
+    sub: |s| s.to_string(),
+    link: |s, uri| format!("[{}](<{}>)", s, uri),
+    // no underline in Markdown
+    underline: ("", ""),
+    strike: ("~~", "~~"),
+};
+
+const HTML: Markup = Markup {
+    escape: escape_html,
+    em: ("<em>", "</em>"),
+    sup: |s| format!("<sup>{}</sup>", s),
+    sub: |s| format!("<sub>{}</sub>", s),
+    link: |s, uri| format!("<a href=\"{}\">{}</a>", escape_html(uri), s),
+    underline: ("<u>", "</u>"),
+    strike: ("<s>", "</s>"),
+};
+
+/// The text of a word, wrapped in the underline or strikethrough markup of its decoration.
+fn word_text(word: &Word, markup: &Markup) -> String {
+    let (before, after) = match word.decoration {
+        Some(Decoration::Underline) => markup.underline,
+        Some(Decoration::Strikethrough) => markup.strike,
+        None => ("", ""),
+    };
+    format!("{}{}{}", before, shifted_text(word, markup), after)
+}
+
+/// The text of a word with raised and lowered parts marked up.
+fn shifted_text(word: &Word, markup: &Markup) -> String {
+    if word.chars.iter().all(|c| c.baseline_shift == BaselineShift::Normal) {
+        return (markup.escape)(&word.text);
+    }
+    let mut out = String::new();
+    let ends = word.chars.iter().skip(1).map(|c| c.offset).chain(std::iter::once(word.text.len()));
+    let mut start = 0;
+    for (shift, group) in &word.chars.iter().zip(ends).chunk_by(|(c, _)| c.baseline_shift) {
+        let end = group.last().map(|(_, end)| end).unwrap();
+        let text = (markup.escape)(&word.text[start..end]);
+        out.push_str(&match shift {
+            BaselineShift::Normal => text,
+            BaselineShift::Superscript => (markup.sup)(&text),
+            BaselineShift::Subscript => (markup.sub)(&text),
+        });
+        start = end;
+    }
+    out
+}
+
+/// Like [`line_text`], but consecutive italic words are wrapped in `em`,
+/// superscripts and subscripts are marked up and so are links.
+fn styled_line_text(line: &Line, markup: &Markup) -> String {
+    line.words.iter()
+        .chunk_by(|w| w.link.as_deref())
+        .into_iter()
+        .map(|(link, words)| {
+            let chunks = words.chunk_by(|w| w.style.italic);
+            let text = chunks.into_iter()
+                .map(|(italic, words)| {
+                    let text = words.map(|w| word_text(w, markup)).join(" ");
+                    if italic {
+                        format!("{}{}{}", markup.em.0, text, markup.em.1)
+                    } else {
+                        text
+                    }
+                })
+                .join(" ");
+            match link {
+                Some(uri) => (markup.link)(&text, uri),
+                None => text
+            }
+        })
+        .join(" ")
+}
+
+fn run_height(run: &Run) -> Option<f32> {
+    avg(run.lines.iter().flat_map(|l| l.words.iter()).map(|w| w.rect.h))
+}
+
+fn body_height(flow: &Flow) -> Option<f32> {
+    avg(flow.runs.iter()
+        .filter(|r| r.kind != RunType::Header)
+        .filter_map(run_height)
+    )
+}
+
+/// The heading level of `run`, from [`Run::level`] if set.
+/// Otherwise headers at least 1.5 times the height of the body text are level 1 and the rest level 2.
+fn header_level(run: &Run, body_height: Option<f32>) -> u8 {
+    if run.level > 0 {
+        return run.level;
+    }
+    match (run_height(run), body_height) {
+        (Some(h), Some(body)) if h >= 1.5 * body => 1,
+        _ => 2
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '|' | '*' | '_' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// GitHub flavored Markdown table. The first row is used as the header,
+/// whether or not it was detected as one, since the syntax requires it.
+/// Spanning cells are followed by empty cells, since Markdown can't merge cells.
+fn markdown_table(out: &mut String, table: &TableContent) {
+    let cols = table.num_cols() as usize;
+    for (i, row) in table.rows().enumerate() {
+        let mut cells = vec![String::new(); cols];
+        for cell in row {
+            cells[cell.col as usize] = escape_markdown(&cell.text).replace('\n', " ");
+        }
+        out.push_str("| ");
+        out.push_str(&cells.join(" | "));
+        out.push_str(" |\n");
+        if i == 0 {
+            out.push('|');
+            for col in 0 .. cols {
+                out.push_str(match table.alignment.get(col) {
+                    Some(Align::Right) => " ---: |",
+                    Some(Align::Center) => " :---: |",
+                    _ => " --- |",
+                });
+            }
+            out.push('\n');
+        }
+    }
+}
+
+/// Render the flow as Markdown.
+///
+/// Headers become `#` to `######` according to their [`level`](Run::level).
+/// Without a level, they are `#` if their text is at least 1.5 times the height of the body text
+/// and `##` otherwise. List items become `-` or `1.` items, code is fenced by ```` ``` ````
+/// and footnotes become `[^1]:` definitions. Paragraphs are separated by blank lines, except before
+/// a [`ParagraphContinuation`](RunType::ParagraphContinuation), and tables use
+/// the GitHub flavored syntax. Italic words in paragraphs are wrapped in `_`
+/// and superscript numbers become footnote references (`[^1]`).
+pub fn to_markdown(flow: &Flow) -> String {
+    let body_height = body_height(flow);
+
+    let mut out = String::new();
+    let mut in_list = false;
+    for block in flow.blocks() {
+        let run = match block {
+            Block::Run(run) if run.lines.len() > 0 => run,
+            Block::Run(_) => continue,
+            Block::Table(table) => {
+                in_list = false;
+                if out.len() > 0 {
+                    out.push('\n');
+                }
+                markdown_table(&mut out, table);
+                if let Some(ref caption) = table.caption {
+                    out.push('\n');
+                    out.push_str(&escape_markdown(caption));
+                    out.push('\n');
+                }
+                continue;
+            }
+        };
+        // consecutive list items form one list
+        let is_list = matches!(run.kind, RunType::ListItem { .. });
+        let continues = run.kind == RunType::ParagraphContinuation;
+        if out.len() > 0 && !(is_list && in_list) && !continues {
+            out.push('\n');
+        }
+        in_list = is_list;
+        match run.kind {
+            RunType::Header => {
+                out.push_str(&"#".repeat(header_level(run, body_height) as usize));
+                out.push(' ');
+                out.push_str(&run.lines.iter().map(|l| line_text(l, escape_markdown)).join(" "));
+                out.push('\n');
+            }
+            RunType::Footnote { ref marker } => {
+                if let Some(marker) = marker {
+                    out.push_str(&format!("[^{}]: ", marker));
+                }
+                out.push_str(&run.lines.iter().map(|l| styled_line_text(l, &MARKDOWN)).join(" "));
+                out.push('\n');
+            }
+            RunType::Code => {
+                out.push_str("```\n");
+                for line in run.lines.iter() {
+                    out.push_str(&line_text(line, |s| s.to_string()));
+                    out.push('\n');
+                }
+                out.push_str("```\n");
+            }
+            RunType::ListItem { ordinal, .. } => {
+                match ordinal {
+                    Some(n) => out.push_str(&format!("{}. ", n)),
+                    None => out.push_str("- "),
+                }
+                out.push_str(&run.lines.iter().map(|l| styled_line_text(l, &MARKDOWN)).join(" "));
+                out.push('\n');
+            }
+            _ => {
+                let last = run.lines.len() - 1;
+                for (i, line) in run.lines.iter().enumerate() {
+                    out.push_str(&styled_line_text(line, &MARKDOWN));
+                    if run.line_breaks && i < last {
+                        // hard line break
+                        out.push('\\');
+                    }
+                    out.push('\n');
+                }
+            }
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c)
+        }
+    }
+    out
+}
+
+fn html_table(out: &mut String, table: &TableContent) {
+    use std::fmt::Write;
+
+    out.push_str("<table>\n");
+    if let Some(ref caption) = table.caption {
+        writeln!(out, "<caption>{}</caption>", escape_html(caption)).unwrap();
+    }
+    // for each column, the number of rows below the current one that a cell above still covers
+    let mut covered = vec![0; table.num_cols() as usize];
+    for row in table.rows() {
+        out.push_str("<tr>");
+        let mut col = 0;
+        for cell in row {
+            // empty cells are not stored
+            while col < cell.col {
+                if covered[col as usize] == 0 {
+                    out.push_str("<td></td>");
+                }
+                col += 1;
+            }
+            let tag = if cell.row < table.header_rows { "th" } else { "td" };
+            write!(out, "<{}", tag).unwrap();
+            match table.alignment.get(cell.col as usize) {
+                Some(Align::Right) => out.push_str(r#" align="right""#),
+                Some(Align::Center) => out.push_str(r#" align="center""#),
+                _ => {}
+            }
+            if cell.colspan > 1 {
+                write!(out, r#" colspan="{}""#, cell.colspan).unwrap();
+            }
+            if cell.rowspan > 1 {
+                write!(out, r#" rowspan="{}""#, cell.rowspan).unwrap();
+            }
+            write!(out, ">{}</{}>", escape_html(&cell.text), tag).unwrap();
+            for c in cell.col .. cell.col + cell.colspan {
+                covered[c as usize] = cell.rowspan;
+            }
+            col = cell.col + cell.colspan;
+        }
+        for c in covered.iter_mut() {
+            *c = c.saturating_sub(1);
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+}
+
+/// Render the flow as an HTML fragment.
+///
+/// Headers become `<h1>` to `<h6>` (using the same rule as [`to_markdown`]),
+/// tables `<table>` with `colspan`/`rowspan` for merged cells and
+/// list items `<li>` inside `<ul>` or `<ol>`, code `<pre><code>`, footnotes `<p class="footnote">` and everything else `<p>` with `<br>` between lines.
+/// A [`ParagraphContinuation`](RunType::ParagraphContinuation) goes into the `<p>` before it.
+/// Italic words are wrapped in `<em>`, superscripts in `<sup>` and subscripts in `<sub>`.
+pub fn to_html(flow: &Flow) -> String {
+    let body_height = body_height(flow);
+
+    let mut out = String::new();
+    // closing tag of the open list
+    let mut list: Option<&str> = None;
+    for block in flow.blocks() {
+        let list_tag = match block {
+            Block::Run(Run { kind: RunType::ListItem { ordinal: Some(_), .. }, .. }) => Some("</ol>\n"),
+            Block::Run(Run { kind: RunType::ListItem { ordinal: None, .. }, .. }) => Some("</ul>\n"),
+            _ => None
+        };
+        if list != list_tag {
+            if let Some(close) = list {
+                out.push_str(close);
+            }
+            if let Some(close) = list_tag {
+                out.push_str(if close == "</ol>\n" { "<ol>\n" } else { "<ul>\n" });
+            }
+            list = list_tag;
+        }
+        let run = match block {
+            Block::Run(run) if run.lines.len() > 0 => run,
+            Block::Run(_) => continue,
+            Block::Table(table) => {
+                html_table(&mut out, table);
+                continue;
+            }
+        };
+        match run.kind {
+            RunType::Footnote { ref marker } => {
+                out.push_str(r#"<p class="footnote">"#);
+                if let Some(marker) = marker {
+                    out.push_str(&format!("<sup>{}</sup> ", escape_html(marker)));
+                }
+                out.push_str(&run.lines.iter().map(|l| styled_line_text(l, &HTML)).join("<br>\n"));
+                out.push_str("</p>\n");
+            }
+            RunType::Code => {
+                out.push_str("<pre><code>");
+                out.push_str(&run.lines.iter().map(|l| line_text(l, escape_html)).join("\n"));
+                out.push_str("</code></pre>\n");
+            }
+            RunType::ListItem { .. } => {
+                out.push_str("<li>");
+                out.push_str(&run.lines.iter().map(|l| styled_line_text(l, &HTML)).join(" "));
+                out.push_str("</li>\n");
+            }
+            RunType::Header => {
+                let tag = format!("h{}", header_level(run, body_height));
+                out.push_str(&format!("<{}>{}</{}>\n", tag, run.lines.iter().map(|l| line_text(l, escape_html)).join(" "), tag));
+            }
+            RunType::ParagraphContinuation if out.ends_with("</p>\n") => {
+                // reopen the paragraph before
+                out.truncate(out.len() - "</p>\n".len());
+                out.push_str("<br>\n");
+                out.push_str(&run.lines.iter().map(|l| styled_line_text(l, &HTML)).join("<br>\n"));
+                out.push_str("</p>\n");
+            }
+            _ => {
+                out.push_str("<p>");
+                out.push_str(&run.lines.iter().map(|l| styled_line_text(l, &HTML)).join("<br>\n"));
+                out.push_str("</p>\n");
+            }
+        }
+    }
+    if let Some(close) = list {
+        out.push_str(close);
+    }
+    out
+}
+
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    if values.len() == 0 {
+        return None;
+    }
+    values.sort_unstable_by(|a, b| a.total_cmp(b));
+    Some(values[values.len() / 2])
+}
+
+/// Estimate the width of one monospace column for [`to_layout_text`]:
+/// the median over all words of the word width divided by its number of chars.
+pub fn layout_char_width(flow: &Flow) -> Option<f32> {
+    median(flow.runs.iter()
+        .flat_map(|r| r.lines.iter())
+        .flat_map(|l| l.words.iter())
+        .filter(|w| w.text.chars().count() > 0 && w.rect.w > 0.0)
+        .map(|w| w.rect.w / w.text.chars().count() as f32)
+        .collect()
+    )
+}
+
+/// Layout preserving plain text, similar to `pdftotext -layout`.
+///
+/// Each word is placed in the column `x / char_width`, where `char_width` is estimated
+/// by [`layout_char_width`]. Use [`to_layout_text_with_char_width`] to pick the scale yourself.
+pub fn to_layout_text(flow: &Flow, page_width: f32) -> String {
+    match layout_char_width(flow) {
+        Some(char_width) => to_layout_text_with_char_width(flow, page_width, char_width),
+        None => String::new()
+    }
+}
+
+/// Like [`to_layout_text`], with a given width of one column in page units.
+pub fn to_layout_text_with_char_width(flow: &Flow, page_width: f32, char_width: f32) -> String {
+    let mut words: Vec<_> = flow.runs.iter()
+        .flat_map(|r| r.lines.iter())
+        .flat_map(|l| l.words.iter())
+        .filter(|w| w.text.len() > 0)
+        .collect();
+    if words.len() == 0 {
+        return String::new();
+    }
+    words.sort_by(|a, b| (a.rect.y + 0.5 * a.rect.h).total_cmp(&(b.rect.y + 0.5 * b.rect.h)));
+    let line_height = median(words.iter().map(|w| w.rect.h).collect()).unwrap();
+    let max_columns = (page_width / char_width).ceil() as usize;
+
+    // group words whose vertical centers are less than half a line apart
+    let mut lines: Vec<Vec<_>> = vec![];
+    let mut line_y = f32::NEG_INFINITY;
+    let mut prev_y = None;
+    let mut out = String::new();
+    for word in words {
+        let y = word.rect.y + 0.5 * word.rect.h;
+        if y - line_y > 0.5 * line_height {
+            lines.push(vec![]);
+            line_y = y;
+        }
+        lines.last_mut().unwrap().push(word);
+    }
+
+    for mut line in lines {
+        line.sort_by(|a, b| a.rect.x.total_cmp(&b.rect.x));
+        let y = line[0].rect.y;
+        if let Some(prev) = prev_y {
+            // keep larger vertical gaps as a blank line
+            if y - prev > 1.5 * line_height {
+                out.push('\n');
+            }
+        }
+        prev_y = Some(y);
+
+        let mut column = 0;
+        for word in line {
+            let target = ((word.rect.x / char_width).round().max(0.0) as usize).min(max_columns);
+            if column > 0 && target <= column {
+                out.push(' ');
+                column += 1;
+            }
+            while column < target {
+                out.push(' ');
+                column += 1;
+            }
+            out.push_str(&word.text);
+            column += word.text.chars().count();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Tab separated values with one row per word, like `pdftotext -tsv`.
+///
+/// The first row is a header. The columns are, in this order:
+/// `page`, `run`, `line`, `word`, `x`, `y`, `w`, `h`, `text`.
+/// All indices are zero based and refer to `pages`, `Flow::runs`, `Run::lines` and `Line::words`.
+/// Tabs and newlines inside the text are replaced by spaces.
+pub fn to_tsv(pages: &[Flow]) -> String {
+    use std::fmt::Write;
+
+    let mut out = String::from("page\trun\tline\tword\tx\ty\tw\th\ttext\n");
+    for (page_nr, flow) in pages.iter().enumerate() {
+        for (run_nr, run) in flow.runs.iter().enumerate() {
+            for (line_nr, line) in run.lines.iter().enumerate() {
+                for (word_nr, word) in line.words.iter().enumerate() {
+                    let r = word.rect;
+                    let text = word.text.replace(|c| matches!(c, '\t' | '\n' | '\r'), " ");
+                    writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                        page_nr, run_nr, line_nr, word_nr, r.x, r.y, r.w, r.h, text
+                    ).unwrap();
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entry::CellContent;
+    use crate::util::Rect;
+    use crate::test_util::{line, word};
+
+    fn cell(text: &str, row: u32, col: u32, rowspan: u32) -> CellContent {
+        CellContent { text: text.into(), rect: Rect { x: 0., y: 0., w: 0., h: 0. }, row, col, rowspan, colspan: 1 }
+    }
+
+    #[test]
+    fn html_table_skips_rowspan_columns() {
+        // a label on the left spanning three rows of values
+        let table = TableContent {
+            position: 0,
+            cells: vec![
+                cell("Address", 0, 0, 3), cell("Main St 1", 0, 1, 1),
+                cell("12345", 1, 1, 1),
+                cell("Springfield", 2, 1, 1),
+            ],
+            header_rows: 0,
+            caption: None,
+            alignment: vec![],
+        };
+        let mut out = String::new();
+        html_table(&mut out, &table);
+        assert_eq!(out, "<table>\n\
+            <tr><td rowspan=\"3\">Address</td><td>Main St 1</td></tr>\n\
+            <tr><td>12345</td></tr>\n\
+            <tr><td>Springfield</td></tr>\n\
+            </table>\n");
+    }
+
+    #[test]
+    fn markdown_table_keeps_empty_rows() {
+        let table = TableContent {
+            position: 0,
+            cells: vec![cell("a", 0, 0, 1), cell("b", 0, 1, 1), cell("c", 2, 1, 1)],
+            header_rows: 1,
+            caption: None,
+            alignment: vec![],
+        };
+        let mut out = String::new();
+        markdown_table(&mut out, &table);
+        assert_eq!(out, "| a | b |\n| --- | --- |\n|  |  |\n|  | c |\n");
+    }
+
+    #[test]
+    fn italic_words_are_emphasized() {
+        let mut words = line(&["a", "very", "fine", "day"], 0.0, 0.0);
+        words.words[1].style.italic = true;
+        words.words[2].style.italic = true;
+        assert_eq!(styled_line_text(&words, &MARKDOWN), "a _very fine_ day");
+        assert_eq!(styled_line_text(&words, &HTML), "a <em>very fine</em> day");
+    }
+
+    #[test]
+    fn layout_text_with_nan_rects() {
+        let mut broken = line(&["broken"], 0.0, 0.0).words;
+        broken[0].rect.y = f32::NAN;
+        let mut flow = Flow::new();
+        flow.add_line(line(&["fine"], 0.0, 0.0).words, RunType::Paragraph);
+        flow.add_line(broken, RunType::Paragraph);
+        let text = to_layout_text_with_char_width(&flow, 100.0, 5.0);
+        assert!(text.starts_with("fine"));
+    }
+
+    #[test]
+    fn markdown_hard_line_breaks() {
+        let mut flow = Flow::new();
+        flow.add_line(line(&["one"], 0.0, 0.0).words, RunType::Paragraph);
+        flow.runs[0].lines.push(line(&["two"], 0.0, 20.0));
+        flow.runs[0].line_breaks = true;
+        assert_eq!(to_markdown(&flow), "one\\\ntwo\n");
+    }
+
+    #[test]
+    fn struck_words_in_markdown() {
+        let mut words = line(&["old", "new"], 0.0, 0.0);
+        words.words[0].decoration = Some(Decoration::Strikethrough);
+        assert_eq!(styled_line_text(&words, &MARKDOWN), "~~old~~ new");
+        assert_eq!(styled_line_text(&words, &HTML), "<s>old</s> new");
+    }
+
+    #[test]
+    fn markdown_headings() {
+        let mut title = line(&["Title"], 0.0, 0.0);
+        title.words[0].rect.h = 20.0;
+        let mut flow = Flow::new();
+        flow.add_line(title.words, RunType::Header);
+        flow.add_line(line(&["Section"], 0.0, 30.0).words, RunType::Header);
+        flow.add_line(line(&["Body", "text"], 0.0, 50.0).words, RunType::Paragraph);
+        flow.add_line(line(&["Details"], 0.0, 70.0).words, RunType::Header);
+        flow.runs[3].level = 3;
+        assert_eq!(to_markdown(&flow), "# Title\n\n## Section\n\nBody text\n\n### Details\n");
+    }
+
+    #[test]
+    fn html_paragraphs_and_continuations() {
+        let mut flow = Flow::new();
+        flow.add_line(line(&["end", "of", "page"], 0.0, 0.0).words, RunType::Paragraph);
+        flow.add_line(line(&["goes", "on"], 0.0, 20.0).words, RunType::ParagraphContinuation);
+        flow.add_line(line(&["a", "<b>"], 0.0, 40.0).words, RunType::Paragraph);
+        assert_eq!(to_html(&flow), "<p>end of page<br>\ngoes on</p>\n<p>a &lt;b&gt;</p>\n");
+
+        // with nothing to continue, it is a paragraph of its own
+        let mut flow = Flow::new();
+        flow.add_line(line(&["goes", "on"], 0.0, 0.0).words, RunType::ParagraphContinuation);
+        assert_eq!(to_html(&flow), "<p>goes on</p>\n");
+    }
+
+    #[test]
+    fn layout_text_columns() {
+        let mut flow = Flow::new();
+        flow.add_line(vec![word("left", 0.0, 0.0), word("right", 50.0, 0.0)], RunType::Paragraph);
+        flow.add_line(vec![word("below", 25.0, 12.0)], RunType::Paragraph);
+        flow.add_line(vec![word("far", 0.0, 50.0)], RunType::Paragraph);
+        // 5 units per column, as wide as the chars
+        assert_eq!(to_layout_text(&flow, 100.0), "left      right\n     below\n\nfar\n");
+    }
+
+    #[test]
+    fn tsv_columns() {
+        let mut first = Flow::new();
+        first.add_line(line(&["a", "b"], 0.0, 0.0).words, RunType::Paragraph);
+        let mut second = Flow::new();
+        second.add_line(line(&["tab\there"], 10.0, 20.0).words, RunType::Paragraph);
+        assert_eq!(to_tsv(&[first, second]), "page\trun\tline\tword\tx\ty\tw\th\ttext\n\
+            0\t0\t0\t0\t0\t0\t5\t10\ta\n\
+            0\t0\t0\t1\t10\t0\t5\t10\tb\n\
+            1\t0\t0\t0\t10\t20\t40\t10\ttab here\n");
+    }
+}
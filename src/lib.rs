@@ -1,55 +1,406 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
-use entry::Flow;
-use pdf::{backend::Backend, object::{Page, Resolve}, PdfError};
-use pdf_render::{tracer::{TraceCache, Tracer, DrawItem}, Fill, render_pattern, render_page, FillMode};
+use itertools::Itertools;
+use entry::{Flow, RunType, Warning};
+use pdf::{backend::Backend, object::{Page, Pattern, Ref, Resolve}, PdfError};
+use pdf_render::{tracer::{TraceCache, TextChar, Tracer, DrawItem}, Fill, render_pattern, render_page, FillMode, TextMode, TextSpan};
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
 
 mod tree;
 mod util;
 mod text;
 pub mod entry;
+pub mod style;
+pub mod outline;
+pub mod export;
+mod options;
+#[cfg(feature="articles")]
+pub mod article;
+#[cfg(feature="ocr")]
+mod ocr;
+#[cfg(test)]
+mod test_util;
 
+pub use text::repair_mojibake;
+
+use util::{is_finite, Deadline, Rect, Rotation};
+use tree::ClassCache;
+
+pub use options::{Options, Normalization, Origin, ParagraphStyle, ReadingOrder, WritingMode};
+
+pub use tree::{Node, NodeTag, NodeVisitor, TableCell};
+
+#[deprecated(note="use `run_with_options`")]
 pub fn run<B: Backend>(file: &pdf::file::CachedFile<B>, page: &Page, resolve: &impl Resolve) -> Result<Flow, PdfError> {
+    run_with_options(file, page, resolve, &Options::default())
+}
+
+/// Extract the text of `page`.
+///
+/// Custom font styles and a time budget are set with [`Options::styles`] and [`Options::timeout`].
+pub fn run_with_options<B: Backend>(file: &pdf::file::CachedFile<B>, page: &Page, resolve: &impl Resolve, opts: &Options) -> Result<Flow, PdfError> {
+    Extractor::new(opts.clone()).process(file, page, resolve)
+}
+
+/// Like [`run_with_options`], but traces the patterns of the page on several threads.
+#[cfg(feature="parallel")]
+pub fn run_parallel<B: Backend>(file: &pdf::file::CachedFile<B>, page: &Page, resolve: &(impl Resolve + Sync), opts: &Options) -> Result<Flow, PdfError> {
+    Extractor::new(opts.clone()).process_parallel(file, page, resolve)
+}
+
+/// Extracts one page after another, reusing the font cache and the scratch buffers
+/// between them instead of allocating them for every page.
+///
+/// ```ignore
+/// let mut extractor = Extractor::new(Options::default());
+/// for page in file.pages() {
+///     let flow = extractor.process(&file, &page?, &resolver)?;
+/// }
+/// ```
+pub struct Extractor {
+    cache: TraceCache,
+    items: PageItems,
+    opts: Options,
+}
+impl Extractor {
+    pub fn new(opts: Options) -> Self {
+        Extractor {
+            cache: TraceCache::new(),
+            items: PageItems::default(),
+            opts,
+        }
+    }
+    pub fn options(&self) -> &Options {
+        &self.opts
+    }
+    /// Extract the text of `page`, like [`run_with_options`].
+    pub fn process<B: Backend>(&mut self, file: &pdf::file::CachedFile<B>, page: &Page, resolve: &impl Resolve) -> Result<Flow, PdfError> {
+        let cache = &self.cache;
+        run_page_spans(cache, file, page, resolve, &self.opts, &mut self.items, |patterns, deadline| trace_patterns(cache, resolve, patterns, deadline))
+    }
+    /// Like [`process`](Extractor::process), but traces the patterns of the page on several threads.
+    #[cfg(feature="parallel")]
+    pub fn process_parallel<B: Backend>(&mut self, file: &pdf::file::CachedFile<B>, page: &Page, resolve: &(impl Resolve + Sync)) -> Result<Flow, PdfError> {
+        let cache = &self.cache;
+        run_page_spans(cache, file, page, resolve, &self.opts, &mut self.items, |patterns, deadline| trace_patterns_parallel(cache, resolve, patterns, deadline))
+    }
+}
+
+/// Extract the text of every page in `file`.
+///
+/// The returned flows are indexed by page number. Pages that fail to load or render
+/// are logged and result in an empty flow. Heading levels are assigned across all pages, and
+/// paragraphs running over a page break are marked, see [`entry::mark_page_continuations`].
+pub fn run_file<B: Backend>(file: &pdf::file::CachedFile<B>, opts: &Options) -> Result<Vec<Flow>, PdfError> {
+    let page_opts = if opts.repeated_header_footer {
+        // decided across pages below
+        opts.clone().strip_header_footer(false)
+    } else {
+        opts.clone()
+    };
+
+    let mut flows = vec![];
+    for (page_nr, flow) in self::flows(file, &page_opts).enumerate() {
+        match flow {
+            Ok(flow) => flows.push(flow),
+            Err(e) => {
+                log::warn!("failed to extract page {}: {:?}", page_nr + 1, e);
+                flows.push(Flow::new());
+            }
+        }
+    }
+    if opts.repeated_header_footer {
+        entry::strip_repeated_header_footer(&mut flows);
+    }
+    entry::mark_page_continuations(&mut flows);
+    entry::assign_heading_levels(&mut flows);
+    Ok(flows)
+}
+
+/// Extract the pages of `file` one at a time.
+///
+/// Unlike [`run_file`], this only keeps the current page in memory, but heading levels
+/// are assigned per page and headers and footers are always judged per page.
+pub fn flows<'a, B: Backend>(file: &'a pdf::file::CachedFile<B>, opts: &Options) -> impl Iterator<Item=Result<Flow, PdfError>> + 'a {
+    let resolver = file.resolver();
+    let mut extractor = Extractor::new(opts.clone());
+    file.pages().map(move |page| page.and_then(|page| extractor.process(file, &page, &resolver)))
+}
+
+/// Analyze the layout of `page` without converting it into a [`Flow`].
+///
+/// Returns the root of the layout tree and the text spans its indices refer to.
+/// With [`WritingMode::Vertical`], the rects of the spans are rotated like the layout sees them.
+pub fn run_tree<B: Backend>(file: &pdf::file::CachedFile<B>, page: &Page, resolve: &impl Resolve, opts: &Options) -> Result<(Node, Vec<TextSpan>), PdfError> {
+    let cache = TraceCache::new();
+    let deadline = Deadline::new(opts.timeout);
+    let mut items = PageItems::default();
+    trace_page(&cache, page, resolve, opts, &deadline, &mut items, |patterns, deadline| trace_patterns(&cache, resolve, patterns, deadline))?;
+    items.rotate(opts);
+    let root = tree::build(&items.spans, items.bbox, &items.lines, opts, &ClassCache::default(), &deadline);
+    Ok((root, items.spans))
+}
+
+/// Like [`run_with_options`], but also returns the text spans of the page.
+///
+/// [`Word::spans`](entry::Word::spans) holds indices into the returned spans.
+pub fn run_with_spans<B: Backend>(file: &pdf::file::CachedFile<B>, page: &Page, resolve: &impl Resolve, opts: &Options) -> Result<(Flow, Vec<TextSpan>), PdfError> {
     let cache = TraceCache::new();
+    let mut items = PageItems::default();
+    let flow = run_page_spans(&cache, file, page, resolve, opts, &mut items, |patterns, deadline| trace_patterns(&cache, resolve, patterns, deadline))?;
+    Ok((flow, items.spans))
+}
+
+/// Extract `page`, leaving its spans in `items`.
+fn run_page_spans<B: Backend>(cache: &TraceCache, file: &pdf::file::CachedFile<B>, page: &Page, resolve: &impl Resolve, opts: &Options, items: &mut PageItems, trace_patterns: impl FnOnce(&[Ref<Pattern>], &Deadline) -> Vec<Result<Vec<DrawItem>, PdfError>>) -> Result<Flow, PdfError> {
+    let deadline = Deadline::new(opts.timeout);
+    trace_page(cache, page, resolve, opts, &deadline, items, trace_patterns)?;
+    let page_bbox = items.bbox;
+    #[cfg(feature="ocr")]
+    if items.spans.is_empty() {
+        if let Some(ref language) = opts.ocr_fallback {
+            items.spans = ocr::ocr_page(page, resolve, page_bbox, language)?;
+            // recognized text has no glyphs in the content stream
+            items.glyphs = items.spans.iter().map(|s| vec![None; s.chars.len()]).collect();
+        }
+    }
+    if items.spans.is_empty() {
+        // nothing for the layout analysis to do
+        let mut flow = Flow::new();
+        flow.bbox = Some(page_bbox.into());
+        flow.warnings.push(Warning::NoText);
+        return Ok(flow);
+    }
+    let rotation = items.rotate(opts);
+    if opts.join_fractions {
+        join_fractions(&mut items.spans, &mut items.glyphs, &mut items.lines);
+    }
+    let PageItems { ref mut spans, ref glyphs, ref lines, ref fills, ref images, bbox, transform } = *items;
+
+    let mut flow = Flow::new();
+    flow.bbox = Some(page_bbox.into());
+    flow.reflow = opts.reflow;
+    let marks = if opts.use_struct_tree {
+        marked_content(page, resolve)?
+    } else {
+        HashMap::new()
+    };
+    let tagged = spans.iter().any(|s| matches!(marks.get(&s.op_nr), Some(tree::MarkedContent::Tagged { .. })));
+    match opts.reading_order {
+        // the document says how it is meant to be read
+        _ if tagged => tree::struct_order(&mut flow, spans, &marks, opts),
+        ReadingOrder::Geometric => {
+            let classes = ClassCache::default();
+            let root = tree::build(spans, bbox, lines, opts, &classes, &deadline);
+            tree::items(&mut flow, spans, &root, bbox.min_x(), opts, &classes);
+        }
+        ReadingOrder::ContentStream => tree::content_order(&mut flow, spans, opts),
+    }
+    set_glyphs(&mut flow, glyphs);
+    flow.fold_drop_caps();
+    flow.detect_lists();
+    set_run_styles(&mut flow, spans, opts);
+    mark_decorations(&mut flow, lines);
+    flow.attach_captions(&opts.caption_prefixes);
+    flow.mark_continuations();
+    flow.assign_heading_levels();
+    if let Some(rotation) = rotation {
+        map_flow_rects(&mut flow, |r| rotation.unrotate(r));
+        for span in spans.iter_mut() {
+            span.rect = rotation.unrotate_rect(span.rect);
+        }
+    }
+    mark_highlights(&mut flow, fills);
+    mark_links(&mut flow, &page_links(page, resolve, transform));
+    if opts.figures {
+        flow.add_figures(&images.iter().map(|&r| r.into()).collect::<Vec<Rect>>());
+    }
+    if opts.origin == Origin::BottomLeft {
+        flip_y(&mut flow, page_bbox);
+    }
+    if deadline.expired() {
+        flow.warnings.push(Warning::TimedOut);
+    }
+    Ok(flow)
+}
+
+/// Entry points into the layout analysis for the benchmarks. Not a stable API.
+#[doc(hidden)]
+pub mod bench {
+    use super::*;
+
+    /// Build the layout tree of `spans` on a page of size `bbox`, with ruling lines
+    /// as `[x1, y1, x2, y2]` and their stroke width.
+    pub fn build(spans: &[TextSpan], bbox: RectF, lines: &[([f32; 4], f32)], opts: &Options) -> Node {
+        tree::build(spans, bbox, lines, opts, &ClassCache::default(), &Deadline::new(None))
+    }
+    /// Turn the layout tree from [`build`] into a flow.
+    pub fn flow(spans: &[TextSpan], root: &Node, bbox: RectF, opts: &Options) -> Flow {
+        let mut flow = Flow::new();
+        tree::items(&mut flow, spans, root, bbox.min_x(), opts, &ClassCache::default());
+        flow
+    }
+}
+
+/// Everything on the page the layout analysis looks at.
+#[derive(Default)]
+struct PageItems {
+    spans: Vec<TextSpan>,
+    /// where the chars of each span were drawn, kept in step with `spans`
+    glyphs: Vec<Glyphs>,
+    /// straight line segments as `[x1, y1, x2, y2]` and their stroke width
+    lines: Vec<([f32; 4], f32)>,
+    /// solid, non-white fills and their color
+    fills: Vec<(RectF, [f32; 3])>,
+    /// images big enough to be figures, in page coordinates
+    images: Vec<RectF>,
+    bbox: RectF,
+    /// from PDF user space to page coordinates, including the /Rotate of the page
+    transform: Transform2F,
+}
+
+impl PageItems {
+    /// Empty the buffers, keeping their allocations.
+    fn clear(&mut self) {
+        self.spans.clear();
+        self.glyphs.clear();
+        self.lines.clear();
+        self.fills.clear();
+        self.images.clear();
+        self.bbox = RectF::default();
+        self.transform = Transform2F::default();
+    }
+    /// Rotate everything for vertical writing, see [`Rotation`].
+    fn rotate(&mut self, opts: &Options) -> Option<Rotation> {
+        if opts.writing_mode != WritingMode::Vertical {
+            return None;
+        }
+        let rotation = Rotation::new(self.bbox);
+        for span in self.spans.iter_mut() {
+            span.rect = rotation.rect(span.rect);
+        }
+        for line in self.lines.iter_mut() {
+            line.0 = rotation.line(line.0);
+        }
+        self.bbox = rotation.rect(self.bbox);
+        Some(rotation)
+    }
+}
+
+/// Fill in [`Run::style`](entry::Run::style) from the spans of the words in each run.
+fn set_run_styles(flow: &mut Flow, spans: &[TextSpan], opts: &Options) {
+    for run in flow.runs.iter_mut() {
+        let indices: Vec<usize> = run.lines.iter()
+            .flat_map(|l| l.words.iter())
+            .flat_map(|w| w.spans.iter().cloned())
+            .dedup()
+            .collect();
+        run.style = style::RunStyle::from_spans(indices.iter().filter_map(|&i| spans.get(i)), &opts.styles);
+    }
+}
 
+/// Apply `f` to the rects of words and table cells.
+fn map_flow_rects(flow: &mut Flow, f: impl Fn(Rect) -> Rect) {
+    for run in flow.runs.iter_mut() {
+        if let RunType::Figure { ref mut rect } = run.kind {
+            *rect = f(*rect);
+        }
+        for line in run.lines.iter_mut() {
+            for word in line.words.iter_mut() {
+                word.rect = f(word.rect);
+            }
+        }
+    }
+    for table in flow.tables.iter_mut() {
+        for cell in table.cells.iter_mut() {
+            cell.rect = f(cell.rect);
+        }
+    }
+}
+
+/// Mirror the rects of `flow` vertically within `page_bbox`, for [`Origin::BottomLeft`].
+fn flip_y(flow: &mut Flow, page_bbox: RectF) {
+    let y_sum = page_bbox.min_y() + page_bbox.max_y();
+    map_flow_rects(flow, |r| Rect { y: y_sum - (r.y + r.h), ..r });
+}
+
+/// Trace `page` into `items`, replacing what was in there before.
+///
+/// `trace_patterns` traces the patterns the page uses, in order.
+fn trace_page(cache: &TraceCache, page: &Page, resolve: &impl Resolve, opts: &Options, deadline: &Deadline, items: &mut PageItems, trace_patterns: impl FnOnce(&[Ref<Pattern>], &Deadline) -> Vec<Result<Vec<DrawItem>, PdfError>>) -> Result<(), PdfError> {
+    items.clear();
     let mut clip_paths = vec![];
-    let mut tracer = Tracer::new(&cache, &mut clip_paths);
+    let mut tracer = Tracer::new(cache, &mut clip_paths);
 
-    render_page(&mut tracer, resolve, &page, Default::default())?;
+    items.transform = render_page(&mut tracer, resolve, &page, Default::default())?;
 
     let bbox = tracer.view_box();
 
-    let items = tracer.finish();
-    let mut patterns = HashSet::new();
-    for item in items.iter() {
+    let draw_items = tracer.finish();
+    // in order of first use, so the output does not depend on hashing
+    let mut patterns = vec![];
+    let mut seen = HashSet::new();
+    for item in draw_items.iter() {
         if let DrawItem::Vector(ref v) = item {
             if let Some(FillMode { color: Fill::Pattern(id), .. }) = v.fill {
-                patterns.insert(id);
+                if seen.insert(id) {
+                    patterns.push(id);
+                }
             }
             if let Some((FillMode { color: Fill::Pattern(id), .. }, _)) = v.stroke {
-                patterns.insert(id);
+                if seen.insert(id) {
+                    patterns.push(id);
+                }
             }
         }
     }
 
-    let mut spans = vec![];
-    let mut lines = vec![];
+    let pattern_items = trace_patterns(&patterns, deadline);
+
+    items.bbox = bbox;
+    let PageItems { ref mut spans, ref mut glyphs, ref mut lines, ref mut fills, ref mut images, .. } = *items;
+    let page_area = bbox.width() * bbox.height();
     let mut visit_item = |item| {
         match item {
-            DrawItem::Text(t, _) if bbox.intersects(t.rect) => {
-                spans.push(t);
+            // degenerate transforms can produce NaN or infinite coordinates
+            DrawItem::Text(t, _) if bbox.intersects(t.rect) && is_finite(t.rect) => {
+                if keeps_span(opts, &t) {
+                    spans.push(t);
+                }
+            }
+            DrawItem::Image(image) if bbox.intersects(image.rect) => {
+                // smaller ones are icons and decorations
+                const MIN_FIGURE_SIZE: f32 = 20.0;
+                if image.rect.width() >= MIN_FIGURE_SIZE && image.rect.height() >= MIN_FIGURE_SIZE {
+                    images.push(image.rect);
+                }
             }
             DrawItem::Vector(path) if bbox.intersects(path.outline.bounds()) => {
+                if let Some(FillMode { color: Fill::Solid(r, g, b), .. }) = path.fill {
+                    let bounds = path.outline.bounds();
+                    // ignore white fills and page backgrounds
+                    if (r, g, b) != (1.0, 1.0, 1.0) && bounds.width() * bounds.height() < 0.5 * page_area {
+                        fills.push((bounds, [r, g, b]));
+                    }
+                }
+                if let Some(line) = path.fill.as_ref().and_then(|_| thin_rect_line(path.outline.bounds())) {
+                    // table borders drawn as filled rectangles
+                    lines.push(line);
+                    return;
+                }
+                // outlines of fills count as hairlines
+                let width = path.stroke.as_ref().map(|(_, style)| style.line_width).unwrap_or(0.0);
                 for contour in path.outline.contours() {
                     use pathfinder_content::{outline::ContourIterFlags, segment::SegmentKind};
                     for segment in contour.iter(ContourIterFlags::empty()) {
                         match segment.kind {
-                            SegmentKind::Line => lines.push([
+                            SegmentKind::Line if is_finite(segment.baseline.bounds()) => lines.push(([
                                 segment.baseline.from_x(),
                                 segment.baseline.from_y(),
                                 segment.baseline.to_x(),
                                 segment.baseline.to_y()
-                            ]),
+                            ], width)),
                             _ => {}
                         }
                     }
@@ -60,29 +411,647 @@ pub fn run<B: Backend>(file: &pdf::file::CachedFile<B>, page: &Page, resolve: &i
         }
     };
 
-    for &p in patterns.iter() {
-        let pattern = match resolve.get(p) {
-            Ok(p) => p,
-            Err(e) => {
-                log::warn!("failed to load pattern: {:?}", e);
+    for pat_items in pattern_items {
+        for item in pat_items? {
+            visit_item(item);
+        }
+    }
+
+    for item in draw_items {
+        visit_item(item);
+    }
+    dedup_spans(spans);
+    *glyphs = glyph_map(spans);
+    merge_spans(spans, glyphs);
+
+    Ok(())
+}
+
+/// Trace `pattern` with a tracer of its own, so patterns can be traced independently.
+fn trace_pattern(cache: &TraceCache, resolve: &impl Resolve, pattern: Ref<Pattern>) -> Result<Vec<DrawItem>, PdfError> {
+    let pattern = match resolve.get(pattern) {
+        Ok(p) => p,
+        Err(e) => {
+            log::warn!("failed to load pattern: {:?}", e);
+            return Ok(vec![]);
+        }
+    };
+    let mut clip_paths = vec![];
+    let mut pat_tracer = Tracer::new(cache, &mut clip_paths);
+    render_pattern(&mut pat_tracer, &*pattern, resolve)?;
+    Ok(pat_tracer.finish())
+}
+
+/// Trace `patterns` one after the other. The deadline is checked before starting each of them.
+fn trace_patterns(cache: &TraceCache, resolve: &impl Resolve, patterns: &[Ref<Pattern>], deadline: &Deadline) -> Vec<Result<Vec<DrawItem>, PdfError>> {
+    patterns.iter().take_while(|_| !deadline.check()).map(|&p| trace_pattern(cache, resolve, p)).collect()
+}
+
+/// Trace `patterns` in batches of one thread per core. The deadline is checked before starting each batch.
+#[cfg(feature="parallel")]
+fn trace_patterns_parallel(cache: &TraceCache, resolve: &(impl Resolve + Sync), patterns: &[Ref<Pattern>], deadline: &Deadline) -> Vec<Result<Vec<DrawItem>, PdfError>> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut pattern_items = Vec::with_capacity(patterns.len());
+    for batch in patterns.chunks(threads) {
+        if deadline.check() {
+            break;
+        }
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = batch.iter().map(|&p| scope.spawn(move || trace_pattern(cache, resolve, p))).collect();
+            pattern_items.extend(handles.into_iter().map(|h| h.join().unwrap_or_else(|_| Err(PdfError::Other {
+                msg: "tracing a pattern panicked".into()
+            }))));
+        });
+    }
+    pattern_items
+}
+
+/// Whether `span` is extracted with its render mode and rotation, see
+/// [`Options::text_modes`] and [`Options::drop_rotated_text`].
+fn keeps_span(opts: &Options, span: &TextSpan) -> bool {
+    opts.extracts_mode(&span.mode) && opts.drop_rotated_text.map_or(true, |max| off_axis_angle(span) <= max)
+}
+
+/// How many degrees the baseline of `span` is away from the nearest axis (0 to 45).
+fn off_axis_angle(span: &TextSpan) -> f32 {
+    let m = span.transform.matrix;
+    let angle = m.m21().atan2(m.m11()).to_degrees().abs() % 90.0;
+    angle.min(90.0 - angle)
+}
+
+/// Remove spans that repeat the text of another span at (almost) the same place.
+///
+/// Some files draw text twice, for example once invisible for accessibility and once visible.
+/// Of each group of duplicates, the first visible span is kept.
+fn dedup_spans(spans: &mut Vec<TextSpan>) {
+    let same_place = |a: &TextSpan, b: &TextSpan| {
+        let tolerance = 0.1 * a.font_size.max(b.font_size);
+        (a.rect.min_x() - b.rect.min_x()).abs() <= tolerance
+            && (a.rect.min_y() - b.rect.min_y()).abs() <= tolerance
+            && (a.rect.max_x() - b.rect.max_x()).abs() <= tolerance
+    };
+    let mut by_text: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, span) in spans.iter().enumerate() {
+        if !span.text.trim().is_empty() {
+            by_text.entry(span.text.as_str()).or_default().push(i);
+        }
+    }
+    let mut remove = HashSet::new();
+    for group in by_text.values().filter(|g| g.len() > 1) {
+        for (n, &i) in group.iter().enumerate() {
+            if remove.contains(&i) {
                 continue;
             }
+            for &j in &group[n + 1..] {
+                if remove.contains(&j) || !same_place(&spans[i], &spans[j]) {
+                    continue;
+                }
+                if spans[i].mode == TextMode::Invisible && spans[j].mode != TextMode::Invisible {
+                    remove.insert(i);
+                    break;
+                }
+                remove.insert(j);
+            }
+        }
+    }
+    if remove.len() > 0 {
+        let mut i = 0;
+        spans.retain(|_| {
+            i += 1;
+            !remove.contains(&(i - 1))
+        });
+    }
+}
+
+/// Join consecutive spans that continue each other: same font, size, color and render mode,
+/// on the same baseline, with the second starting where the first ends.
+///
+/// The renderer sometimes emits a span per glyph run, and lots of tiny spans throw off
+/// the word gap estimate. `glyphs` is joined along with the spans.
+fn merge_spans(spans: &mut Vec<TextSpan>, glyphs: &mut Vec<Glyphs>) {
+    let same_font = |a: &TextSpan, b: &TextSpan| match (&a.font, &b.font) {
+        (Some(x), Some(y)) => Arc::ptr_eq(x, y),
+        (None, None) => true,
+        _ => false
+    };
+    let same_color = |a: &TextSpan, b: &TextSpan| a.alpha == b.alpha && match (&a.color, &b.color) {
+        (Fill::Solid(r1, g1, b1), Fill::Solid(r2, g2, b2)) => (r1, g1, b1) == (r2, g2, b2),
+        (Fill::Pattern(x), Fill::Pattern(y)) => x == y,
+        _ => false
+    };
+    let mut merged: Vec<TextSpan> = Vec::with_capacity(spans.len());
+    let mut merged_glyphs: Vec<Glyphs> = Vec::with_capacity(glyphs.len());
+    for (span, span_glyphs) in spans.drain(..).zip(glyphs.drain(..)) {
+        if let (Some(prev), Some(prev_glyphs)) = (merged.last_mut(), merged_glyphs.last_mut()) {
+            let tolerance = 0.1 * prev.font_size;
+            let gap = span.rect.min_x() - prev.rect.max_x();
+            if same_font(prev, &span) && same_color(prev, &span)
+                && prev.font_size == span.font_size && prev.mode == span.mode
+                && prev.transform.matrix == span.transform.matrix
+                && gap.abs() <= tolerance
+            {
+                // offset of the new span in the text space of the previous one
+                let offset = prev.transform.matrix.inverse() * (span.transform.vector - prev.transform.vector);
+                if offset.y().abs() <= tolerance {
+                    let text_len = prev.text.len();
+                    prev.text.push_str(&span.text);
+                    prev.chars.extend(span.chars.iter().map(|c| TextChar {
+                        offset: c.offset + text_len,
+                        pos: c.pos + offset.x(),
+                        width: c.width,
+                    }));
+                    prev.rect = prev.rect.union_rect(span.rect);
+                    prev_glyphs.extend(span_glyphs);
+                    continue;
+                }
+            }
+        }
+        merged.push(span);
+        merged_glyphs.push(span_glyphs);
+    }
+    *spans = merged;
+    *glyphs = merged_glyphs;
+}
+
+/// For each char of a span, the `op_nr` and glyph index it was drawn as,
+/// see [`Char::glyph`](entry::Char::glyph).
+type Glyphs = Vec<Option<(usize, usize)>>;
+
+/// The [`Glyphs`] of spans as they come from the content stream.
+fn glyph_map(spans: &[TextSpan]) -> Vec<Glyphs> {
+    spans.iter().map(|s| (0 .. s.chars.len()).map(|i| Some((s.op_nr, i))).collect()).collect()
+}
+
+/// Fill in [`Char::glyph`](entry::Char::glyph) from the [`Char::source`](entry::Char::source) of each char.
+fn set_glyphs(flow: &mut Flow, glyphs: &[Glyphs]) {
+    for run in flow.runs.iter_mut() {
+        for line in run.lines.iter_mut() {
+            for word in line.words.iter_mut() {
+                for c in word.chars.iter_mut() {
+                    c.glyph = c.source.and_then(|(span, i)| glyphs.get(span)?.get(i).cloned().flatten());
+                }
+            }
+        }
+    }
+}
+
+/// Replace a number above a short horizontal bar above another number by a single "n/d" span,
+/// so the stacked fraction does not end up on two lines. The bar is removed from `lines`.
+///
+/// Both numbers have to be set smaller than the average text and centered on the bar,
+/// which tells fractions apart from sums in a table. `glyphs` is joined along with the spans.
+fn join_fractions(spans: &mut Vec<TextSpan>, glyphs: &mut Vec<Glyphs>, lines: &mut Vec<([f32; 4], f32)>) {
+    let avg_font_size = match util::avg(spans.iter().map(|s| s.font_size)) {
+        Some(size) => size,
+        None => return
+    };
+    let is_number = |s: &TextSpan| util::is_number(s.text.trim()) && s.chars.len() > 0
+        && s.font_size < 0.9 * avg_font_size;
+    let mut removed = vec![false; spans.len()];
+    lines.retain(|&([x1, y1, x2, y2], _)| {
+        let (min_x, max_x, y) = (x1.min(x2), x1.max(x2), 0.5 * (y1 + y2));
+        if (y1 - y2).abs() >= 0.1 * (max_x - min_x) {
+            return true;
+        }
+        let centered = |s: &TextSpan| {
+            let bar_center = 0.5 * (min_x + max_x);
+            (s.rect.center().x() - bar_center).abs() < 0.25 * s.font_size
+                && max_x - min_x < 4.0 * s.font_size
+        };
+        let find = |removed: &[bool], dist: &dyn Fn(&TextSpan) -> f32| spans.iter().enumerate()
+            .position(|(i, s)| !removed[i] && is_number(s) && centered(s) && {
+                let d = dist(s);
+                d >= -0.2 * s.rect.height() && d <= 0.5 * s.rect.height()
+            });
+        let num = find(&removed, &|s| y - s.rect.max_y());
+        let den = find(&removed, &|s| s.rect.min_y() - y);
+        let (num, den) = match (num, den) {
+            (Some(num), Some(den)) if num != den => (num, den),
+            _ => return true
         };
-        let mut pat_tracer = Tracer::new(&cache, &mut clip_paths);
 
-        render_pattern(&mut pat_tracer, &*pattern, resolve)?;
-        let pat_items = pat_tracer.finish();
-        for item in pat_items {
-            visit_item(item);
+        let den_span = spans[den].clone();
+        let span = &mut spans[num];
+        let last = span.chars.last().map(|c| (c.pos + c.width, c.width)).unwrap_or_default();
+        let (slash_pos, char_width) = last;
+        span.text.push('/');
+        span.chars.push(TextChar { offset: span.text.len() - 1, pos: slash_pos, width: char_width });
+        let den_start = den_span.chars[0].pos;
+        let text_len = span.text.len();
+        span.text.push_str(&den_span.text);
+        span.chars.extend(den_span.chars.iter().map(|c| TextChar {
+            offset: c.offset + text_len,
+            pos: slash_pos + char_width + (c.pos - den_start),
+            width: c.width,
+        }));
+        span.rect = span.rect.union_rect(den_span.rect);
+        // the slash was not drawn
+        let den_glyphs = glyphs[den].clone();
+        glyphs[num].push(None);
+        glyphs[num].extend(den_glyphs);
+        removed[den] = true;
+        false
+    });
+    if removed.contains(&true) {
+        let mut i = 0;
+        spans.retain(|_| {
+            i += 1;
+            !removed[i - 1]
+        });
+        let mut i = 0;
+        glyphs.retain(|_| {
+            i += 1;
+            !removed[i - 1]
+        });
+    }
+}
+
+/// The center line of a filled rectangle thin enough to be a ruling line, and its thickness.
+fn thin_rect_line(r: RectF) -> Option<([f32; 4], f32)> {
+    const MAX_THICKNESS: f32 = 3.0;
+    // shorter ones are more likely dashes and bullets
+    const MIN_LENGTH: f32 = 10.0;
+    let (w, h) = (r.width(), r.height());
+    if h <= MAX_THICKNESS && w >= MIN_LENGTH.max(4.0 * h) {
+        let y = r.center().y();
+        Some(([r.min_x(), y, r.max_x(), y], h))
+    } else if w <= MAX_THICKNESS && h >= MIN_LENGTH.max(4.0 * w) {
+        let x = r.center().x();
+        Some(([x, r.min_y(), x, r.max_y()], w))
+    } else {
+        None
+    }
+}
+
+/// The marked-content sequence each operator of the content stream of `page` is in, by its index.
+///
+/// The innermost sequence with an `/MCID` wins. Operators inside an `/Artifact` are artifacts,
+/// and operators that are in no sequence with an id are left out.
+fn marked_content(page: &Page, resolve: &impl Resolve) -> Result<HashMap<usize, tree::MarkedContent>, PdfError> {
+    use pdf::{content::Op, primitive::Primitive};
+    use tree::MarkedContent;
+
+    let ops = match page.contents {
+        Some(ref contents) => contents.operations(resolve)?,
+        None => return Ok(HashMap::new())
+    };
+    let mut marks = HashMap::new();
+    // the sequences the current operator is in, innermost last
+    let mut stack: Vec<Option<MarkedContent>> = vec![];
+    for (op_nr, op) in ops.iter().enumerate() {
+        match *op {
+            Op::BeginMarkedContent { ref tag, ref properties } => {
+                let mcid = match properties {
+                    Some(Primitive::Dictionary(dict)) => match dict.get("MCID") {
+                        Some(&Primitive::Integer(mcid)) => Some(mcid as i64),
+                        _ => None
+                    },
+                    _ => None
+                };
+                stack.push(match (tag.as_str(), mcid) {
+                    ("Artifact", _) => Some(MarkedContent::Artifact),
+                    (tag, Some(mcid)) => Some(MarkedContent::Tagged { tag: tag.into(), mcid }),
+                    (_, None) => None,
+                });
+            }
+            Op::EndMarkedContent => {
+                stack.pop();
+            }
+            _ => {
+                let mark = if stack.iter().any(|m| *m == Some(MarkedContent::Artifact)) {
+                    Some(MarkedContent::Artifact)
+                } else {
+                    stack.iter().rev().find_map(|m| m.clone())
+                };
+                if let Some(mark) = mark {
+                    marks.insert(op_nr, mark);
+                }
+            }
         }
     }
+    Ok(marks)
+}
 
-    for item in items {
-        visit_item(item);
+/// The URI links on `page` and their rects in the coordinates of the tracer.
+///
+/// Annotations that fail to load are skipped. `transform` is the one the page was traced with,
+/// so the rects are relative to the same box as the text and turned by the same /Rotate.
+fn page_links(page: &Page, resolve: &impl Resolve, transform: Transform2F) -> Vec<(RectF, String)> {
+    use pdf::primitive::Primitive;
+
+    let annots = match page.annotations.load(resolve) {
+        Ok(a) => a,
+        Err(e) => {
+            log::warn!("failed to load annotations: {:?}", e);
+            return vec![];
+        }
+    };
+    let mut links = vec![];
+    for annot in annots.iter() {
+        if annot.subtype.as_str() != "Link" {
+            continue;
+        }
+        let (rect, action) = match (annot.rect, annot.other.get("A")) {
+            (Some(rect), Some(action)) => (rect, action),
+            _ => continue
+        };
+        let uri = match action.clone().resolve(resolve).and_then(|a| a.into_dictionary()) {
+            Ok(dict) => match dict.get("URI") {
+                Some(Primitive::String(s)) => s.to_string_lossy(),
+                _ => continue
+            },
+            Err(_) => continue
+        };
+        let min = Vector2F::new(rect.left.min(rect.right), rect.bottom.min(rect.top));
+        let max = Vector2F::new(rect.left.max(rect.right), rect.bottom.max(rect.top));
+        links.push((transform * RectF::from_points(min, max), uri));
     }
+    links
+}
 
-    let root = tree::build(&spans, bbox, &lines);
-    let mut flow = Flow::new();
-    tree::items(&mut flow, &spans, &root, bbox.min_x());
-    Ok(flow)
-}
\ No newline at end of file
+/// Set [`Word::link`](entry::Word::link) on the words that are mostly inside a link.
+///
+/// A link covering several words or lines is set on each of them.
+fn mark_links(flow: &mut Flow, links: &[(RectF, String)]) {
+    if links.len() == 0 {
+        return;
+    }
+    for run in flow.runs.iter_mut() {
+        for line in run.lines.iter_mut() {
+            for word in line.words.iter_mut() {
+                let r = word.rect;
+                let rect = RectF::new(Vector2F::new(r.x, r.y), Vector2F::new(r.w, r.h));
+                let area = r.w * r.h;
+                word.link = links.iter()
+                    .find(|(link, _)| link.intersection(rect).map(|i| i.width() * i.height() > 0.5 * area).unwrap_or(false))
+                    .map(|(_, uri)| uri.clone());
+            }
+        }
+    }
+}
+
+/// Set [`Word::decoration`](entry::Word::decoration) on words with a horizontal line
+/// under them or through them.
+///
+/// Lines that reach further than the text of the line they are in belong to something else,
+/// like a table or a separator.
+fn mark_decorations(flow: &mut Flow, lines: &[([f32; 4], f32)]) {
+    use entry::Decoration;
+
+    // (min x, max x, y) of the horizontal lines
+    let hlines: Vec<(f32, f32, f32)> = lines.iter()
+        .filter(|&&([x1, y1, x2, y2], _)| (y1 - y2).abs() < 0.1 * (x2 - x1).abs())
+        .map(|&([x1, y1, x2, y2], _)| (x1.min(x2), x1.max(x2), 0.5 * (y1 + y2)))
+        .collect();
+    if hlines.len() == 0 {
+        return;
+    }
+    for run in flow.runs.iter_mut() {
+        for line in run.lines.iter_mut() {
+            let (min_x, max_x) = match (line.words.first(), line.words.last()) {
+                (Some(first), Some(last)) => (first.rect.x, last.rect.x + last.rect.w),
+                _ => continue
+            };
+            for word in line.words.iter_mut() {
+                let r = word.rect;
+                word.decoration = hlines.iter()
+                    .filter(|&&(x1, x2, _)| x1 >= min_x - r.h && x2 <= max_x + r.h)
+                    // covers most of the word
+                    .filter(|&&(x1, x2, _)| x2.min(r.x + r.w) - x1.max(r.x) > 0.5 * r.w)
+                    .find_map(|&(_, _, y)| {
+                        let rel = (y - r.y) / r.h;
+                        if rel >= 0.75 && rel <= 1.25 {
+                            Some(Decoration::Underline)
+                        } else if rel >= 0.35 && rel <= 0.7 {
+                            Some(Decoration::Strikethrough)
+                        } else {
+                            None
+                        }
+                    });
+            }
+        }
+    }
+}
+
+/// Tag words that are mostly covered by a colored fill with the color of that fill.
+fn mark_highlights(flow: &mut Flow, fills: &[(RectF, [f32; 3])]) {
+    if fills.len() == 0 {
+        return;
+    }
+    for run in flow.runs.iter_mut() {
+        for line in run.lines.iter_mut() {
+            for word in line.words.iter_mut() {
+                let r = word.rect;
+                let rect = RectF::new(Vector2F::new(r.x, r.y), Vector2F::new(r.w, r.h));
+                let area = r.w * r.h;
+                word.highlight = fills.iter()
+                    .rev()
+                    .find(|(fill, _)| fill.intersection(rect).map(|i| i.width() * i.height() > 0.5 * area).unwrap_or(false))
+                    .map(|&(_, color)| color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{line, span};
+    use crate::entry::Decoration;
+
+    #[test]
+    fn merge_continued_spans() {
+        let mut spans = vec![span("Hel", 0.0, 0.0, 10.0), span("lo", 15.0, 0.0, 10.0), span("World", 40.0, 0.0, 10.0)];
+        let mut glyphs = glyph_map(&spans);
+        merge_spans(&mut spans, &mut glyphs);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].text, "Hello");
+        assert_eq!(spans[0].rect.max_x(), 25.0);
+        let pos: Vec<f32> = spans[0].chars.iter().map(|c| c.pos).collect();
+        assert_eq!(pos, [0.0, 5.0, 10.0, 15.0, 20.0]);
+        assert_eq!(spans[0].chars[4].offset, 4);
+    }
+
+    #[test]
+    fn merge_keeps_colors_apart() {
+        let mut red = span("lo", 15.0, 0.0, 10.0);
+        red.color = Fill::Solid(1.0, 0.0, 0.0);
+        let mut spans = vec![span("Hel", 0.0, 0.0, 10.0), red];
+        let mut glyphs = glyph_map(&spans);
+        merge_spans(&mut spans, &mut glyphs);
+        assert_eq!(spans.len(), 2);
+
+        let mut faint = span("lo", 15.0, 0.0, 10.0);
+        faint.alpha = 0.5;
+        let mut spans = vec![span("Hel", 0.0, 0.0, 10.0), faint];
+        let mut glyphs = glyph_map(&spans);
+        merge_spans(&mut spans, &mut glyphs);
+        assert_eq!(spans.len(), 2);
+    }
+
+    #[test]
+    fn yellow_highlight() {
+        let mut flow = Flow::new();
+        flow.add_line(line(&["marked", "plain"], 0.0, 0.0).words, RunType::Paragraph);
+        let yellow = [1.0, 1.0, 0.0];
+        // a marker stroke a bit taller than the word, ending in the gap before the next one
+        let fill = RectF::new(Vector2F::new(-1.0, -2.0), Vector2F::new(33.0, 14.0));
+        mark_highlights(&mut flow, &[(fill, yellow)]);
+        let words = &flow.runs[0].lines[0].words;
+        assert_eq!(words[0].highlight, Some(yellow));
+        assert_eq!(words[1].highlight, None);
+    }
+
+    #[test]
+    fn topmost_fill_wins() {
+        let mut flow = Flow::new();
+        flow.add_line(line(&["cell", "note"], 0.0, 0.0).words, RunType::Paragraph);
+        let grey = [0.9, 0.9, 0.9];
+        let green = [0.6, 1.0, 0.6];
+        let fills = [
+            // the shading of a table row, drawn first
+            (RectF::new(Vector2F::new(0.0, -5.0), Vector2F::new(100.0, 20.0)), grey),
+            // a marker over the first word
+            (RectF::new(Vector2F::new(0.0, 0.0), Vector2F::new(20.0, 10.0)), green),
+            // less than half of the second word
+            (RectF::new(Vector2F::new(25.0, 0.0), Vector2F::new(5.0, 10.0)), green),
+        ];
+        mark_highlights(&mut flow, &fills);
+        let words = &flow.runs[0].lines[0].words;
+        assert_eq!(words[0].highlight, Some(green));
+        assert_eq!(words[1].highlight, Some(grey));
+    }
+
+    #[test]
+    fn thin_rects_are_lines() {
+        let rect = |x, y, w, h| RectF::new(Vector2F::new(x, y), Vector2F::new(w, h));
+        assert_eq!(thin_rect_line(rect(10.0, 100.0, 200.0, 1.0)), Some(([10.0, 100.5, 210.0, 100.5], 1.0)));
+        assert_eq!(thin_rect_line(rect(50.0, 0.0, 2.0, 80.0)), Some(([51.0, 0.0, 51.0, 80.0], 2.0)));
+        // a bullet, a dash and a filled box
+        assert_eq!(thin_rect_line(rect(0.0, 0.0, 3.0, 3.0)), None);
+        assert_eq!(thin_rect_line(rect(0.0, 0.0, 8.0, 1.0)), None);
+        assert_eq!(thin_rect_line(rect(0.0, 0.0, 100.0, 20.0)), None);
+    }
+
+    #[test]
+    fn bottom_left_origin() {
+        let mut flow = Flow::new();
+        flow.add_line(line(&["top"], 10.0, 20.0).words, RunType::Paragraph);
+        flip_y(&mut flow, RectF::new(Vector2F::new(0.0, 0.0), Vector2F::new(600.0, 800.0)));
+        let rect = flow.runs[0].lines[0].words[0].rect;
+        assert_eq!((rect.x, rect.y, rect.w, rect.h), (10.0, 770.0, 15.0, 10.0));
+    }
+
+    #[test]
+    fn duplicate_spans() {
+        let mut hidden = span("Hello", 0.0, 0.0, 10.0);
+        hidden.mode = TextMode::Invisible;
+        let mut spans = vec![
+            hidden,
+            span("Hello", 0.5, 0.0, 10.0),
+            span("Hello", 0.0, 0.5, 10.0),
+            span("Hello", 0.0, 40.0, 10.0),
+        ];
+        dedup_spans(&mut spans);
+        assert_eq!(spans.len(), 2);
+        assert!(spans.iter().all(|s| s.mode != TextMode::Invisible));
+        assert_eq!(spans[1].rect.min_y(), 40.0);
+    }
+
+    #[test]
+    fn rotated_text_angle() {
+        let rotated = |degrees: f32| {
+            let mut s = span("CONFIDENTIAL", 0.0, 0.0, 10.0);
+            s.transform = Transform2F::from_rotation(degrees.to_radians());
+            off_axis_angle(&s)
+        };
+        assert_eq!(rotated(0.0), 0.0);
+        assert!(rotated(90.0) < 0.01);
+        assert!(rotated(-90.0) < 0.01);
+        assert!((rotated(30.0) - 30.0).abs() < 0.01);
+        assert!((rotated(-30.0) - 30.0).abs() < 0.01);
+        assert!((rotated(135.0) - 45.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn drop_rotated_watermark() {
+        let body = span("Body", 0.0, 0.0, 10.0);
+        let mut watermark = span("CONFIDENTIAL", 0.0, 50.0, 40.0);
+        watermark.transform = Transform2F::from_rotation(45f32.to_radians());
+
+        let opts = Options::default().drop_rotated_text(Some(10.0));
+        assert!(keeps_span(&opts, &body));
+        assert!(!keeps_span(&opts, &watermark));
+        // kept unless asked for
+        assert!(keeps_span(&Options::default(), &watermark));
+    }
+
+    #[test]
+    fn underlined_and_struck_words() {
+        let mut flow = Flow::new();
+        flow.add_line(line(&["under", "struck", "plain"], 0.0, 0.0).words, RunType::Paragraph);
+        flow.add_line(line(&["separated"], 0.0, 30.0).words, RunType::Paragraph);
+        let lines = [
+            ([0.0, 10.0, 25.0, 10.0], 0.5),
+            ([30.0, 5.0, 60.0, 5.0], 0.5),
+            // a separator across the page, right under the second line
+            ([0.0, 40.0, 500.0, 40.0], 0.5),
+        ];
+        mark_decorations(&mut flow, &lines);
+        let decorations: Vec<_> = flow.runs.iter()
+            .flat_map(|r| r.lines.iter())
+            .flat_map(|l| l.words.iter())
+            .map(|w| w.decoration)
+            .collect();
+        assert_eq!(decorations, [Some(Decoration::Underline), Some(Decoration::Strikethrough), None, None]);
+    }
+
+    #[test]
+    fn stacked_fractions() {
+        let mut spans = vec![
+            span("Add", 0.0, 0.0, 10.0),
+            span("1", 53.5, 0.0, 6.0),
+            span("2", 53.5, 8.0, 6.0),
+            span("cups", 65.0, 0.0, 10.0),
+        ];
+        let mut lines = vec![([52.0, 7.0, 58.0, 7.0], 0.5)];
+        for (op_nr, span) in spans.iter_mut().enumerate() {
+            span.op_nr = op_nr;
+        }
+        let mut glyphs = glyph_map(&spans);
+        join_fractions(&mut spans, &mut glyphs, &mut lines);
+        assert_eq!(lines.len(), 0);
+        let texts: Vec<&str> = spans.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, ["Add", "1/2", "cups"]);
+        let pos: Vec<f32> = spans[1].chars.iter().map(|c| c.pos).collect();
+        assert_eq!(pos, [0.0, 3.0, 6.0]);
+        assert_eq!(glyphs.len(), 3);
+        assert_eq!(glyphs[1], [Some((1, 0)), None, Some((2, 0))]);
+
+        // numbers in the size of the text around them are a sum
+        let mut spans = vec![span("Add", 0.0, 0.0, 10.0), span("1", 52.5, -4.0, 10.0), span("2", 52.5, 8.0, 10.0)];
+        let mut lines = vec![([50.0, 7.0, 60.0, 7.0], 0.5)];
+        let mut glyphs = glyph_map(&spans);
+        join_fractions(&mut spans, &mut glyphs, &mut lines);
+        assert_eq!((spans.len(), lines.len()), (3, 1));
+    }
+
+    #[test]
+    fn redact_across_merged_spans() {
+        let mut spans = vec![span("Hel", 0.0, 0.0, 10.0), span("lo", 15.0, 0.0, 10.0), span("there", 40.0, 0.0, 10.0)];
+        for (span, op_nr) in spans.iter_mut().zip([3, 4, 5]) {
+            span.op_nr = op_nr;
+        }
+        let mut glyphs = glyph_map(&spans);
+        merge_spans(&mut spans, &mut glyphs);
+        assert_eq!(spans.len(), 2);
+
+        let opts = Options::default().strip_header_footer(false);
+        let bbox = RectF::new(Vector2F::zero(), Vector2F::new(600.0, 800.0));
+        let root = bench::build(&spans, bbox, &[], &opts);
+        let mut flow = bench::flow(&spans, &root, bbox, &opts);
+        set_glyphs(&mut flow, &glyphs);
+        assert_eq!(flow.redaction_targets("hello"), [(3, 0 .. 3), (4, 0 .. 2)]);
+        assert_eq!(flow.redaction_targets("lo there"), [(4, 0 .. 2), (5, 0 .. 5)]);
+    }
+}
@@ -0,0 +1,90 @@
+//! Recognize the text of pages that only consist of images, see [`Options::ocr_fallback`](crate::Options::ocr_fallback).
+
+use std::ffi::CString;
+
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use pathfinder_rasterize::Rasterizer;
+use pdf::{object::{Page, Resolve}, PdfError};
+use pdf_render::{render_page, tracer::{TextChar, TextSpan}, Cache, Fill, SceneBackend, TextMode};
+use tesseract_plumbing::TessBaseApi;
+
+/// resolution the page is rendered at for the OCR
+const DPI: f32 = 300.0;
+
+/// Render `page` and run Tesseract over it.
+///
+/// Returns one span per recognized word, in the coordinates of `bbox` (the view box of the page),
+/// so they can go through the layout analysis like real text.
+pub fn ocr_page(page: &Page, resolve: &impl Resolve, bbox: RectF, language: &str) -> Result<Vec<TextSpan>, PdfError> {
+    let scale = DPI / 72.0;
+    let mut cache = Cache::new();
+    let mut backend = SceneBackend::new(&mut cache);
+    render_page(&mut backend, resolve, page, Transform2F::from_scale(scale))?;
+    let image = Rasterizer::new().rasterize(backend.finish(), None);
+
+    let tsv = match recognize(image.as_raw(), image.width(), image.height(), language) {
+        Some(tsv) => tsv,
+        None => {
+            log::warn!("OCR failed");
+            return Ok(vec![]);
+        }
+    };
+
+    let to_page = |x: f32, y: f32| bbox.origin() + Vector2F::new(x, y) * (1.0 / scale);
+    let mut spans = vec![];
+    // level page block par line word left top width height conf text
+    for row in tsv.lines().skip(1) {
+        let cols: Vec<&str> = row.split('\t').collect();
+        if cols.len() < 12 || cols[0] != "5" {
+            continue;
+        }
+        let text = cols[11].trim();
+        let num = |i: usize| cols[i].parse::<f32>().ok();
+        let (left, top, width, height) = match (num(6), num(7), num(8), num(9)) {
+            (Some(l), Some(t), Some(w), Some(h)) => (l, t, w, h),
+            _ => continue
+        };
+        if text.is_empty() {
+            continue;
+        }
+        let rect = RectF::from_points(to_page(left, top), to_page(left + width, top + height));
+        spans.push(synthetic_span(text, rect, spans.len()));
+    }
+    Ok(spans)
+}
+
+/// Run Tesseract over an RGBA image and return its TSV output.
+fn recognize(rgba: &[u8], width: u32, height: u32, language: &str) -> Option<String> {
+    let mut api = TessBaseApi::create();
+    let language = CString::new(language).ok()?;
+    api.init_2(None, Some(&language)).ok()?;
+    api.set_image(rgba, width as i32, height as i32, 4, 4 * width as i32).ok()?;
+    api.recognize().ok()?;
+    let text = api.get_tsv_text(0).ok()?;
+    Some(text.as_ref().to_string_lossy().into_owned())
+}
+
+/// A span without a font for a recognized word, with its chars spread evenly over `rect`.
+fn synthetic_span(text: &str, rect: RectF, op_nr: usize) -> TextSpan {
+    let num_chars = text.chars().count().max(1);
+    let char_width = rect.width() / num_chars as f32;
+    let chars = text.char_indices().enumerate().map(|(n, (offset, _))| TextChar {
+        offset,
+        pos: n as f32 * char_width,
+        width: char_width,
+    }).collect();
+    TextSpan {
+        rect,
+        width: rect.width(),
+        bbox: None,
+        font_size: rect.height(),
+        font: None,
+        text: text.into(),
+        chars,
+        color: Fill::Solid(0.0, 0.0, 0.0),
+        alpha: 1.0,
+        transform: Transform2F::from_translation(rect.lower_left()),
+        mode: TextMode::Fill,
+        op_nr,
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use pdf_render::TextMode;
+
+use crate::style::StyleClassifier;
+use crate::text::LIGATURES;
+
+/// Unicode normalization applied to the extracted text.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Normalization {
+    /// keep the codepoints as they come out of the font
+    None,
+    /// canonical composition
+    NFC,
+    /// compatibility composition, which also flattens full width forms, superscripts, etc.
+    NFKC,
+}
+
+/// Direction in which the text of a page runs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WritingMode {
+    /// lines run left to right and stack top to bottom
+    Horizontal,
+    /// lines run top to bottom and stack right to left, as in Chinese and Japanese
+    Vertical,
+}
+
+/// Where the y axis of the rects in a [`Flow`](crate::entry::Flow) starts.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Origin {
+    /// y grows downwards from the top of the page, like in images and on the web
+    TopLeft,
+    /// y grows upwards from the bottom of the page, like in the PDF itself
+    BottomLeft,
+}
+
+/// The order in which the text of a page is emitted.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReadingOrder {
+    /// follow the layout analysis: columns, rows and tables
+    Geometric,
+    /// the order the text is drawn in the content stream, one line per baseline
+    ContentStream,
+}
+
+/// How paragraphs are told apart within a block of text.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParagraphStyle {
+    /// the first line of a paragraph is indented
+    FirstLineIndent,
+    /// the first line of a paragraph sticks out to the left of the others, as in bibliographies
+    Hanging,
+    /// paragraphs are separated by extra vertical space and not indented
+    BlockSpacing,
+}
+
+/// Tuning knobs for the text extraction.
+///
+/// ```ignore
+/// let opts = Options::default().strip_header_footer(true).x_y_ratio(1.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub(crate) strip_header_footer: bool,
+    pub(crate) x_y_ratio: f32,
+    pub(crate) styles: StyleClassifier,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) detect_tables: bool,
+    pub(crate) caption_prefixes: Vec<String>,
+    pub(crate) ligatures: HashMap<char, String>,
+    pub(crate) dehyphenate: bool,
+    pub(crate) normalization: Normalization,
+    pub(crate) bidi: bool,
+    pub(crate) writing_mode: WritingMode,
+    pub(crate) header_band: f32,
+    pub(crate) footer_band: f32,
+    pub(crate) left_band: f32,
+    pub(crate) right_band: f32,
+    pub(crate) repeated_header_footer: bool,
+    pub(crate) line_tolerance: f32,
+    pub(crate) line_merge_threshold: f32,
+    pub(crate) origin: Origin,
+    pub(crate) ocr_fallback: Option<String>,
+    pub(crate) figures: bool,
+    pub(crate) text_modes: Option<Vec<TextMode>>,
+    pub(crate) preserve_line_breaks: bool,
+    pub(crate) drop_rotated_text: Option<f32>,
+    pub(crate) expected_columns: Option<usize>,
+    pub(crate) reading_order: ReadingOrder,
+    pub(crate) reflow: bool,
+    pub(crate) paragraph_style: Option<ParagraphStyle>,
+    pub(crate) paragraph_indent: f32,
+    pub(crate) strip_leaders: bool,
+    pub(crate) join_fractions: bool,
+    pub(crate) use_struct_tree: bool,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            strip_header_footer: true,
+            x_y_ratio: 1.0,
+            styles: StyleClassifier::default(),
+            timeout: None,
+            detect_tables: false,
+            caption_prefixes: vec!["Table".into(), "Figure".into(), "Fig.".into()],
+            ligatures: LIGATURES.iter().map(|&(c, s)| (c, s.into())).collect(),
+            dehyphenate: false,
+            normalization: Normalization::NFKC,
+            bidi: false,
+            writing_mode: WritingMode::Horizontal,
+            header_band: 0.2,
+            footer_band: 0.2,
+            left_band: 0.2,
+            right_band: 0.2,
+            repeated_header_footer: false,
+            line_tolerance: 0.01,
+            line_merge_threshold: 10.0,
+            origin: Origin::TopLeft,
+            ocr_fallback: None,
+            figures: false,
+            text_modes: None,
+            preserve_line_breaks: false,
+            drop_rotated_text: None,
+            expected_columns: None,
+            reading_order: ReadingOrder::Geometric,
+            reflow: false,
+            paragraph_style: None,
+            paragraph_indent: 0.5,
+            strip_leaders: false,
+            join_fractions: false,
+            use_struct_tree: false,
+        }
+    }
+}
+impl Options {
+    /// Drop page headers and footers (running titles, page numbers). Enabled by default.
+    pub fn strip_header_footer(mut self, strip: bool) -> Self {
+        self.strip_header_footer = strip;
+        self
+    }
+    /// Ruling lines that rise by less than `tolerance` times their length count as horizontal
+    /// (and likewise for vertical lines). The default of 0.01 allows about half a degree of skew.
+    pub fn line_tolerance(mut self, tolerance: f32) -> Self {
+        self.line_tolerance = tolerance;
+        self
+    }
+    /// Parallel ruling lines closer than this are treated as one. Defaults to 10.0,
+    /// lower it for dense tables with small fonts.
+    pub fn line_merge_threshold(mut self, threshold: f32) -> Self {
+        self.line_merge_threshold = threshold;
+        self
+    }
+    /// In [`run_file`](crate::run_file), only strip headers and footers that repeat on most pages
+    /// (see [`strip_repeated_header_footer`](crate::entry::strip_repeated_header_footer))
+    /// instead of guessing on each page. Disabled by default.
+    pub fn repeated_header_footer(mut self, repeated: bool) -> Self {
+        self.repeated_header_footer = repeated;
+        self
+    }
+    /// Fractions of the page height at the top and bottom where headers and footers are looked for.
+    /// Both default to 0.2.
+    pub fn header_footer_bands(mut self, header: f32, footer: f32) -> Self {
+        self.header_band = header;
+        self.footer_band = footer;
+        self
+    }
+    /// Fractions of the page width at the left and right where margin notes are looked for.
+    /// Both default to 0.2.
+    pub fn margin_bands(mut self, left: f32, right: f32) -> Self {
+        self.left_band = left;
+        self.right_band = right;
+        self
+    }
+    /// How much a vertical gap counts compared to a horizontal gap of the same size
+    /// when deciding where to split the page. Defaults to 1.0.
+    ///
+    /// Values above 1.0 make splitting into rows more likely, which suits prose.
+    /// Values below 1.0 favor splitting into columns, which suits dense tables.
+    pub fn x_y_ratio(mut self, ratio: f32) -> Self {
+        self.x_y_ratio = ratio;
+        self
+    }
+    /// Number of text columns on the page, if known. Disabled (`None`) by default.
+    ///
+    /// The first split of the page then uses the `columns - 1` widest vertical gaps instead of
+    /// every gap above the threshold, so a wide figure or equation does not add a spurious column.
+    pub fn expected_columns(mut self, columns: Option<usize>) -> Self {
+        self.expected_columns = columns;
+        self
+    }
+    /// Where the order of the text comes from. Defaults to [`ReadingOrder::Geometric`].
+    ///
+    /// [`ReadingOrder::ContentStream`] skips the layout analysis, including table detection and
+    /// header and footer stripping. Use it for linear documents whose producer already drew
+    /// the text in reading order, but whose layout confuses the analysis.
+    pub fn reading_order(mut self, order: ReadingOrder) -> Self {
+        self.reading_order = order;
+        self
+    }
+    /// On tagged pages, build the flow from the marked content instead of the layout analysis.
+    /// Disabled by default.
+    ///
+    /// Each marked-content sequence becomes a run, in the order of their ids, and its structure
+    /// type ("P", "H1", "Note", ...) decides the [`RunType`](crate::entry::RunType). Artifacts like
+    /// running headers are dropped. Pages without any tagged text go through the usual analysis.
+    pub fn use_struct_tree(mut self, use_struct_tree: bool) -> Self {
+        self.use_struct_tree = use_struct_tree;
+        self
+    }
+    /// Which fonts count as bold or italic.
+    pub fn styles(mut self, styles: StyleClassifier) -> Self {
+        self.styles = styles;
+        self
+    }
+    /// Look for tables in regions that have both several columns and several rows,
+    /// and in grids of ruling lines. Disabled by default, since the detection can break up regular text.
+    pub fn detect_tables(mut self, detect: bool) -> Self {
+        self.detect_tables = detect;
+        self
+    }
+    /// A line right above or below a table starting with one of these becomes its caption.
+    /// Defaults to "Table", "Figure" and "Fig.".
+    pub fn caption_prefixes(mut self, prefixes: Vec<String>) -> Self {
+        self.caption_prefixes = prefixes;
+        self
+    }
+    /// Replace `glyph` with `letters` in the extracted text.
+    ///
+    /// The standard ligatures (U+FB00 to U+FB06) are expanded by default. Use this for fonts
+    /// that put their ligatures in the private use area, e.g. `.ligature('\u{F04E}', "ffi")`.
+    pub fn ligature(mut self, glyph: char, letters: &str) -> Self {
+        self.ligatures.insert(glyph, letters.into());
+        self
+    }
+    /// Keep every line of a paragraph on its own line, for addresses and poems.
+    ///
+    /// Sets [`Run::line_breaks`](crate::entry::Run::line_breaks) on paragraphs. That ends the lines
+    /// in [`to_markdown`](crate::export::to_markdown) with a hard line break and keeps them apart in
+    /// [`Flow::to_text`](crate::entry::Flow::to_text) even with [`reflow`](Options::reflow) on.
+    /// The HTML output has a `<br>` between lines either way. Disabled by default, so paragraphs
+    /// are joined in Markdown and, with `reflow`, in the text.
+    pub fn preserve_line_breaks(mut self, preserve: bool) -> Self {
+        self.preserve_line_breaks = preserve;
+        self
+    }
+    /// Make [`Flow::to_text`](crate::entry::Flow::to_text) put each paragraph on a single line,
+    /// which is what word processors expect when pasting. Disabled by default.
+    ///
+    /// Combine with [`dehyphenate`](Options::dehyphenate) to also join words broken at the line end.
+    pub fn reflow(mut self, reflow: bool) -> Self {
+        self.reflow = reflow;
+        self
+    }
+    /// How paragraphs are marked, or `None` (the default) to guess between
+    /// [`ParagraphStyle::FirstLineIndent`] and [`ParagraphStyle::Hanging`] from the majority of lines.
+    pub fn paragraph_style(mut self, style: Option<ParagraphStyle>) -> Self {
+        self.paragraph_style = style;
+        self
+    }
+    /// How far a line has to be indented to count as indented, in line heights. Defaults to 0.5.
+    pub fn paragraph_indent(mut self, indent: f32) -> Self {
+        self.paragraph_indent = indent;
+        self
+    }
+    /// Remove dot leaders like in "Intro ....... 5" and mark the word after them as a
+    /// [`Word::leader`](crate::entry::Word::leader), so the plain text becomes "Intro\t5".
+    /// Disabled by default.
+    pub fn strip_leaders(mut self, strip: bool) -> Self {
+        self.strip_leaders = strip;
+        self
+    }
+    /// Turn a small number over a short bar over another small number into one word like "1/2",
+    /// as fractions are set in recipes. Disabled by default, since a table cell above a sum
+    /// can look the same.
+    pub fn join_fractions(mut self, join: bool) -> Self {
+        self.join_fractions = join;
+        self
+    }
+    /// Join words hyphenated at the end of a line in a paragraph ("effi-" "cient" becomes "efficient").
+    /// Disabled by default, since it also joins real compounds that happen to break at their hyphen.
+    pub fn dehyphenate(mut self, dehyphenate: bool) -> Self {
+        self.dehyphenate = dehyphenate;
+        self
+    }
+    /// How to normalize the extracted text. Defaults to [`Normalization::NFKC`].
+    pub fn normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+    /// Put lines of right-to-left scripts (Arabic, Hebrew) into reading order.
+    ///
+    /// PDFs usually draw such text from left to right, so without this the words come out reversed.
+    pub fn bidi(mut self, bidi: bool) -> Self {
+        self.bidi = bidi;
+        self
+    }
+    /// Coordinate system of the rects of words and table cells. Defaults to [`Origin::TopLeft`].
+    ///
+    /// Either way x grows to the right, and the `y` of a rect is its smallest y value, so with
+    /// [`Origin::BottomLeft`] it is the bottom edge. [`Flow::bbox`](crate::entry::Flow::bbox)
+    /// is the same in both.
+    pub fn origin(mut self, origin: Origin) -> Self {
+        self.origin = origin;
+        self
+    }
+    /// Leave out text whose baseline is turned more than `angle` degrees away from the horizontal
+    /// and vertical, like diagonal "CONFIDENTIAL" watermarks. Text turned by 90° is kept.
+    /// Disabled (`None`) by default.
+    pub fn drop_rotated_text(mut self, angle: Option<f32>) -> Self {
+        self.drop_rotated_text = angle;
+        self
+    }
+    /// Only extract text drawn with one of these render modes, or all text with `None` (the default).
+    ///
+    /// Scanned documents usually have their OCR layer in [`TextMode::Invisible`]. Leave it out
+    /// to get only the visible text, or pass only it to get only the OCR layer.
+    pub fn text_modes(mut self, modes: Option<Vec<TextMode>>) -> Self {
+        self.text_modes = modes;
+        self
+    }
+    /// Whether text drawn in `mode` is extracted, see [`text_modes`](Options::text_modes).
+    pub(crate) fn extracts_mode(&self, mode: &TextMode) -> bool {
+        self.text_modes.as_ref().map_or(true, |modes| modes.contains(mode))
+    }
+    /// Add a [`RunType::Figure`](crate::entry::RunType::Figure) run for every image on the page
+    /// that is at least 20 units wide and high. Disabled by default.
+    pub fn figures(mut self, figures: bool) -> Self {
+        self.figures = figures;
+        self
+    }
+    /// Run OCR with the Tesseract `language` (like "eng") on pages without any text.
+    ///
+    /// Only has an effect with the `ocr` feature. The recognized words go through the
+    /// layout analysis like regular text. Disabled by default.
+    pub fn ocr_fallback(mut self, language: Option<String>) -> Self {
+        self.ocr_fallback = language;
+        self
+    }
+    /// Direction of the text on the page. Defaults to [`WritingMode::Horizontal`].
+    pub fn writing_mode(mut self, mode: WritingMode) -> Self {
+        self.writing_mode = mode;
+        self
+    }
+    /// Stop analyzing a page once `timeout` has elapsed.
+    ///
+    /// The budget is checked before tracing each pattern and at every recursion step of the
+    /// layout splitter. Rendering the page content itself is not interrupted, so a single slow
+    /// operation can overrun the budget. When it runs out, the remaining regions are emitted as
+    /// plain lines without further splitting and [`Warning::TimedOut`](crate::entry::Warning::TimedOut)
+    /// is added to the flow.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_modes() {
+        let opts = Options::default();
+        assert!(opts.extracts_mode(&TextMode::Fill));
+        assert!(opts.extracts_mode(&TextMode::Invisible));
+
+        let visible = opts.text_modes(Some(vec![TextMode::Fill, TextMode::Stroke]));
+        assert!(visible.extracts_mode(&TextMode::Fill));
+        assert!(!visible.extracts_mode(&TextMode::Invisible));
+    }
+}
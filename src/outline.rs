@@ -0,0 +1,149 @@
+use std::collections::{HashMap, HashSet};
+
+use pdf::{
+    backend::Backend,
+    file::CachedFile,
+    object::{Action, Dest, DestView, MaybeNamedDest, OutlineItem as PdfOutlineItem, PagesNode, PageTree, PlainRef, Ref, Resolve},
+    primitive::{PdfString, Primitive},
+    PdfError,
+};
+use serde::{Serialize, Deserialize};
+
+/// An entry of the document outline (bookmarks).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OutlineItem {
+    pub title: String,
+    /// nesting depth, starting at 0 for top level entries
+    pub level: usize,
+    /// zero based index of the target page, if it could be resolved
+    pub page: Option<usize>,
+    /// the y coordinate on the target page the view scrolls to, if the destination has one.
+    /// In PDF user space, so it grows from the bottom of the page.
+    #[serde(default)]
+    pub dest_y: Option<f32>,
+}
+
+/// Read the outline (`/Outlines`) of the document as a flat list in document order.
+///
+/// Returns an empty list if the document has no outline.
+pub fn extract_outline<B: Backend>(file: &CachedFile<B>) -> Result<Vec<OutlineItem>, PdfError> {
+    let resolver = file.resolver();
+    let catalog = file.get_root();
+
+    let mut out = vec![];
+    let first = match catalog.outlines {
+        Some(ref outlines) => match outlines.first {
+            Some(first) => first,
+            None => return Ok(out)
+        },
+        None => return Ok(out)
+    };
+
+    let mut pages = HashMap::new();
+    add_pages(&resolver, &mut pages, &catalog.pages, &mut 0)?;
+
+    let mut named = HashMap::new();
+    if let Some(ref names) = catalog.names {
+        if let Some(ref dests) = names.dests {
+            dests.walk(&resolver, &mut |key: &PdfString, val: &Option<Dest>| {
+                if let Some(Dest { page: Some(page), ref view }) = val {
+                    named.insert(key.to_string_lossy(), (page.get_inner(), view_top(view)));
+                }
+            })?;
+        }
+    }
+
+    let lookup = |name: &str| named.get(name).map(|&(r, y)| (pages.get(&r).cloned(), y)).unwrap_or((None, None));
+    let target = |dest: &Primitive| -> (Option<usize>, Option<f32>) {
+        match *dest {
+            Primitive::String(ref s) => lookup(&s.to_string_lossy()),
+            Primitive::Name(ref n) => lookup(n.as_str()),
+            Primitive::Array(ref a) => match a.first() {
+                Some(&Primitive::Reference(r)) => (pages.get(&r).cloned(), array_top(a)),
+                _ => (None, None)
+            }
+            _ => (None, None)
+        }
+    };
+
+    let mut visited = HashSet::new();
+    let mut stack = vec![(first.get_inner(), 0)];
+    while let Some((item_ref, level)) = stack.pop() {
+        // malformed files may contain cycles
+        if !visited.insert(item_ref) {
+            continue;
+        }
+        let item = match resolver.get(Ref::<PdfOutlineItem>::new(item_ref)) {
+            Ok(item) => item,
+            Err(e) => {
+                log::warn!("failed to load outline item: {:?}", e);
+                continue;
+            }
+        };
+
+        let (page, dest_y) = match (&item.dest, &item.action) {
+            (Some(dest), _) => target(dest),
+            (None, Some(Action::Goto(MaybeNamedDest::Direct(Dest { page: Some(page), view })))) => (pages.get(&page.get_inner()).cloned(), view_top(view)),
+            (None, Some(Action::Goto(MaybeNamedDest::Named(name)))) => lookup(&name.to_string_lossy()),
+            _ => (None, None)
+        };
+        out.push(OutlineItem {
+            title: item.title.as_ref().map(|t| t.to_string_lossy()).unwrap_or_default(),
+            level,
+            page,
+            dest_y,
+        });
+
+        // visit the children before the next sibling
+        if let Some(next) = item.next {
+            stack.push((next.get_inner(), level));
+        }
+        if let Some(child) = item.first {
+            stack.push((child.get_inner(), level + 1));
+        }
+    }
+
+    Ok(out)
+}
+
+/// The top of the view of a destination.
+fn view_top(view: &DestView) -> Option<f32> {
+    match *view {
+        DestView::XYZ { top, .. } => top,
+        DestView::FitH { top } | DestView::FitBH { top } => Some(top),
+        DestView::FitR(ref r) => Some(r.top),
+        _ => None
+    }
+}
+
+/// The top of the view of an explicit destination like `[page /XYZ left top zoom]`.
+fn array_top(dest: &[Primitive]) -> Option<f32> {
+    let kind = match dest.get(1) {
+        Some(Primitive::Name(ref n)) => n.as_str(),
+        _ => return None
+    };
+    let index = match kind {
+        "XYZ" | "FitH" | "FitBH" => 2,
+        "FitR" => 5,
+        _ => return None
+    };
+    match dest.get(index) {
+        Some(&Primitive::Integer(i)) => Some(i as f32),
+        Some(&Primitive::Number(n)) => Some(n),
+        _ => None
+    }
+}
+
+fn add_pages(resolve: &impl Resolve, pages: &mut HashMap<PlainRef, usize>, tree: &PageTree, current_page: &mut usize) -> Result<(), PdfError> {
+    for &node_ref in &tree.kids {
+        let node = resolve.get(node_ref)?;
+        match *node {
+            PagesNode::Tree(ref tree) => add_pages(resolve, pages, tree, current_page)?,
+            PagesNode::Leaf(_) => {
+                pages.insert(node_ref.get_inner(), *current_page);
+                *current_page += 1;
+            }
+        }
+    }
+    Ok(())
+}
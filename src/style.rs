@@ -0,0 +1,169 @@
+use regex::Regex;
+use pdf_render::{Fill, FontEntry, TextSpan};
+use serde::{Serialize, Deserialize};
+
+/// Weight and slant of a piece of text.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// The typical look of a run of text.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RunStyle {
+    /// name of the font used for most of the text
+    pub font: Option<String>,
+    /// median font size
+    pub font_size: f32,
+    /// most of the text is bold
+    pub bold: bool,
+    /// most of the text is italic
+    pub italic: bool,
+    /// most common solid color of the text
+    pub color: Option<[f32; 3]>,
+}
+impl RunStyle {
+    /// Compute the style from the spans the run was built from.
+    pub(crate) fn from_spans<'a>(spans: impl Iterator<Item=&'a TextSpan>, styles: &StyleClassifier) -> RunStyle {
+        let mut fonts: Vec<(&str, usize)> = vec![];
+        let mut colors: Vec<([f32; 3], usize)> = vec![];
+        let mut sizes = vec![];
+        let (mut bold, mut italic, mut count) = (0, 0, 0);
+        for span in spans {
+            let len = span.text.chars().count();
+            count += len;
+            sizes.push(span.font_size);
+            if let Some(ref font) = span.font {
+                let style = styles.font_style(font);
+                bold += if style.bold { len } else { 0 };
+                italic += if style.italic { len } else { 0 };
+                match fonts.iter_mut().find(|(name, _)| *name == font.name.as_str()) {
+                    Some(entry) => entry.1 += len,
+                    None => fonts.push((&font.name, len)),
+                }
+            }
+            if let Fill::Solid(r, g, b) = span.color {
+                match colors.iter_mut().find(|(c, _)| *c == [r, g, b]) {
+                    Some(entry) => entry.1 += len,
+                    None => colors.push(([r, g, b], len)),
+                }
+            }
+        }
+        sizes.sort_unstable_by(|a, b| a.total_cmp(b));
+        RunStyle {
+            font: fonts.iter().max_by_key(|(_, n)| *n).map(|(name, _)| name.to_string()),
+            font_size: sizes.get(sizes.len() / 2).cloned().unwrap_or(0.0),
+            bold: 2 * bold > count,
+            italic: 2 * italic > count,
+            color: colors.iter().max_by_key(|(_, n)| *n).map(|&(c, _)| c),
+        }
+    }
+}
+
+/// Maps font names to weight and style.
+///
+/// PDFs rarely carry reliable weight information, so the font name is matched
+/// against a list of patterns. A font is bold (or italic) if any of the patterns match.
+#[derive(Clone, Debug)]
+pub struct StyleClassifier {
+    pub bold: Vec<Regex>,
+    pub italic: Vec<Regex>,
+}
+impl StyleClassifier {
+    /// A classifier that never reports bold or italic.
+    pub fn empty() -> Self {
+        StyleClassifier {
+            bold: vec![],
+            italic: vec![],
+        }
+    }
+    pub fn add_bold(mut self, pattern: Regex) -> Self {
+        self.bold.push(pattern);
+        self
+    }
+    pub fn add_italic(mut self, pattern: Regex) -> Self {
+        self.italic.push(pattern);
+        self
+    }
+    pub fn is_bold(&self, font_name: &str) -> bool {
+        self.bold.iter().any(|r| r.is_match(font_name))
+    }
+    pub fn is_italic(&self, font_name: &str) -> bool {
+        self.italic.iter().any(|r| r.is_match(font_name))
+    }
+    pub fn style(&self, font_name: &str) -> Style {
+        Style {
+            bold: self.is_bold(font_name),
+            italic: self.is_italic(font_name),
+        }
+    }
+    /// Like [`is_bold`](Self::is_bold), but uses the weight in the font descriptor if there is one.
+    /// A weight of 600 (semibold) or more counts as bold.
+    pub fn is_bold_font(&self, font: &FontEntry) -> bool {
+        match font_weight(font) {
+            Some(weight) => weight >= 600.0,
+            None => self.is_bold(&font.name)
+        }
+    }
+    /// [`style`](Self::style) with the weight taken from the font descriptor if possible.
+    pub fn font_style(&self, font: &FontEntry) -> Style {
+        Style {
+            bold: self.is_bold_font(font),
+            italic: self.is_italic(&font.name),
+        }
+    }
+}
+impl Default for StyleClassifier {
+    fn default() -> Self {
+        StyleClassifier {
+            bold: vec![
+                Regex::new(r"(?i)bold|black|heavy|demi").unwrap(),
+                // numbered weights like "Helvetica-75"
+                Regex::new(r"[-_ ][789]\d$").unwrap(),
+            ],
+            italic: vec![Regex::new(r"(?i)italic|oblique").unwrap()],
+        }
+    }
+}
+
+/// The `/FontWeight` of the font descriptor (100 to 900).
+fn font_weight(font: &FontEntry) -> Option<f32> {
+    font.pdf_font.info()?.font_descriptor.as_ref()?.font_weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_patterns() {
+        let styles = StyleClassifier::default();
+        assert_eq!(styles.style("Helvetica-Bold"), Style { bold: true, italic: false });
+        assert_eq!(styles.style("Arial-BoldItalicMT"), Style { bold: true, italic: true });
+        assert_eq!(styles.style("Times-Roman"), Style::default());
+    }
+
+    #[test]
+    fn custom_patterns() {
+        let styles = StyleClassifier::empty()
+            .add_bold(Regex::new(r"(?i)fett").unwrap())
+            .add_italic(Regex::new(r"(?i)kursiv").unwrap());
+        assert!(styles.is_bold("Grotesk-Fett"));
+        assert!(styles.is_italic("Grotesk-Kursiv"));
+        // the default names are not known any more
+        assert!(!styles.is_bold("Helvetica-Bold"));
+        assert_eq!(StyleClassifier::empty().style("Helvetica-BoldOblique"), Style::default());
+    }
+
+    #[test]
+    fn weight_in_font_name() {
+        let styles = StyleClassifier::default();
+        assert!(styles.is_bold("Futura-Demi"));
+        assert!(styles.is_bold("Helvetica-75"));
+        assert!(styles.is_bold("Univers_95"));
+        assert!(!styles.is_bold("Helvetica-45"));
+        // a number that is part of the name
+        assert!(!styles.is_bold("Font95"));
+    }
+}
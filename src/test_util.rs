@@ -0,0 +1,73 @@
+//! Hand made inputs for the unit tests.
+
+use pathfinder_geometry::{rect::RectF, transform2d::Transform2F, vector::Vector2F};
+use pdf_render::{tracer::{TextChar, TextSpan}, Fill, TextMode};
+
+use crate::entry::{Char, Line, Word};
+use crate::style::Style;
+use crate::util::Rect;
+
+/// width of every char, in font sizes
+pub const CHAR_WIDTH: f32 = 0.5;
+
+/// A span of `text` with its top left corner at `(x, y)`, without a font.
+///
+/// The chars are `CHAR_WIDTH * font_size` wide, and their positions are relative to the
+/// origin of the span, as the tracer produces them.
+pub fn span(text: &str, x: f32, y: f32, font_size: f32) -> TextSpan {
+    let char_width = CHAR_WIDTH * font_size;
+    let chars: Vec<TextChar> = text.char_indices().enumerate().map(|(n, (offset, _))| TextChar {
+        offset,
+        pos: n as f32 * char_width,
+        width: char_width,
+    }).collect();
+    let rect = RectF::new(Vector2F::new(x, y), Vector2F::new(chars.len() as f32 * char_width, font_size));
+    TextSpan {
+        rect,
+        width: rect.width(),
+        bbox: None,
+        font_size,
+        font: None,
+        text: text.into(),
+        chars,
+        color: Fill::Solid(0.0, 0.0, 0.0),
+        alpha: 1.0,
+        transform: Transform2F::from_translation(rect.origin()),
+        mode: TextMode::Fill,
+        op_nr: 0,
+    }
+}
+
+/// A word at `(x, y)` with chars 5 units wide and 10 units high.
+pub fn word(text: &str, x: f32, y: f32) -> Word {
+    let chars: Vec<Char> = text.char_indices().enumerate().map(|(n, (offset, _))| Char {
+        offset,
+        pos: x + 5.0 * n as f32,
+        width: 5.0,
+        baseline_shift: Default::default(),
+        source: None,
+        glyph: None,
+    }).collect();
+    Word {
+        text: text.into(),
+        rect: Rect { x, y, w: 5.0 * chars.len() as f32, h: 10.0 },
+        highlight: None,
+        spans: vec![],
+        style: Style::default(),
+        chars,
+        link: None,
+        decoration: None,
+        leader: false,
+    }
+}
+
+/// A line of `words` starting at `(x, y)`, one char width apart.
+pub fn line(words: &[&str], x: f32, y: f32) -> Line {
+    let mut x = x;
+    let words = words.iter().map(|text| {
+        let w = word(text, x, y);
+        x += w.rect.w + 5.0;
+        w
+    }).collect();
+    Line { words }
+}
@@ -1,34 +1,26 @@
-use pathfinder_geometry::vector::Vector2F;
-use pdf_render::TextSpan;
+use pathfinder_geometry::{vector::Vector2F, rect::RectF};
+use pdf_render::{tracer::TextChar, TextSpan};
 use itertools::{Itertools};
 use unicode_normalization::UnicodeNormalization;
-use crate::{util::avg, entry::Word, util::Rect};
+use std::iter::once;
+use std::mem::take;
+use std::ops::Range;
+use std::collections::HashMap;
+use std::sync::Arc;
+use unicode_bidi::{bidi_class, BidiClass, BidiInfo, Level};
+use crate::{util::avg, entry::{BaselineShift, Char, Line, Word}, util::Rect, Options, options::{Normalization, WritingMode}, style::Style};
 
-pub fn concat_text<'a>(out: &mut String, items: impl Iterator<Item=&'a TextSpan> + Clone) -> Vec<Word> {
+/// Join the text of `items` (span index and span) into `out` and split it into words.
+pub fn concat_text<'a>(out: &mut String, items: impl Iterator<Item=(usize, &'a TextSpan)> + Clone, opts: &Options) -> Vec<Word> {
+    if opts.writing_mode == WritingMode::Vertical {
+        return concat_vertical(out, items, opts);
+    }
     let mut words = vec![];
+    let out_start = out.len();
 
-    let gaps = items.clone()
-        .flat_map(|s| {
-            let tr_inv = s.transform.matrix.inverse();
-            let pos = (tr_inv * s.transform.vector).x();
-            s.chars.iter()
-                .filter(|c| !s.text[c.offset..].chars().next().unwrap().is_whitespace())
-                // (left edge, right edge, font size)
-                .map(move |c| (c.pos + pos, c.pos + pos + c.width, s.font_size))
-        })
-        .tuple_windows()
-        // skip things that go in reverse
-        .filter(|(a, b)| b.0 > a.0)
-        // compute the distance between the right edge of the left char and the left edge of the right char
-        // and clamp it to a minimum of 0.01 and maximum of half the mean font size
-        .map(|(a, b)| (b.0 - a.1).max(0.01).min(0.25 * (a.2 + b.2)));
-
-    // compute the average font size of all chars
-    let font_size = avg(items.clone().map(|s| s.font_size)).unwrap();
-    //gaps.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
-
-    // set the threshold at twice the average gap, clamped to half the font size
-    let space_gap = (0.5 * font_size).min(2.0 * avg(gaps).unwrap_or(0.0)); //2.0 * gaps[gaps.len()/2];
+    let space_gaps = analyze_word_gap(items.clone());
+    let shifts = baseline_shifts(items.clone());
+    let span_styles = items.clone();
     let mut end = 0.; // trailing edge of the last char
     let mut trailing_space = out.chars().last().map(|c| c.is_whitespace()).unwrap_or(true);
     let mut word_start_pos = 0.0;
@@ -37,21 +29,52 @@ pub fn concat_text<'a>(out: &mut String, items: impl Iterator<Item=&'a TextSpan>
     let mut y_max = -f32::INFINITY;
     let mut word_start = true;
     let mut word_end = 0.0;
+    let mut word_spans = vec![];
+    let mut word_chars = vec![];
+    let mut last_span = None;
+    // threshold for the font of the previous char
+    let mut space_gap = space_gaps.default;
 
-    for span in items {
+    for (span_idx, span) in items {
         let mut pos = 0; // byte index of last char into span.text
         let tr_inv = span.transform.matrix.inverse();
         let x_off = (tr_inv * span.transform.vector).x();
-        for c in span.chars.iter() {
+        let span_gap = space_gaps.get(span);
+        let shift = shifts.get(&span_idx).cloned().unwrap_or_default();
+        // x and width of a char on the page
+        let place = |c: &TextChar| {
+            let start = (span.transform.matrix * Vector2F::new(c.pos + x_off, 0.0)).x();
+            let end = (span.transform.matrix * Vector2F::new(c.pos + x_off + c.width, 0.0)).x();
+            (start, end - start)
+        };
+        // the text at `pos` belongs to an overstruck char and is dropped
+        let mut overstruck = false;
+        for (i, c) in span.chars.iter().enumerate() {
 
             let s = &span.text[pos..c.offset];
-            if c.offset > 0 {
-                let is_whitespace = s.chars().all(|c| c.is_whitespace());
+            if overstruck {
+                overstruck = false;
+            } else if c.offset > 0 {
+                let is_whitespace = s.chars().all(is_blank);
+                if !is_whitespace {
+                    // the text up to here belongs to the char before
+                    let prev = i.saturating_sub(1);
+                    let (pos, width) = place(&span.chars[prev]);
+                    word_chars.push(Char {
+                        offset: out.len() - word_start_idx, pos, width, baseline_shift: shift,
+                        source: Some((span_idx, prev)), glyph: None,
+                    });
+                }
                 if !trailing_space || !is_whitespace {
-                    out.extend(s.nfkc());
+                    push_text(out, s, opts);
                 }
                 trailing_space = is_whitespace;
             }
+            if i > 0 && is_overstruck(span, i) {
+                pos = c.offset;
+                overstruck = true;
+                continue;
+            }
             if !trailing_space && c.pos + x_off > end + space_gap {
                 words.push(Word {
                     text: out[word_start_idx..].into(),
@@ -60,7 +83,14 @@ pub fn concat_text<'a>(out: &mut String, items: impl Iterator<Item=&'a TextSpan>
                         y: y_min,
                         h: y_max - y_min,
                         w: word_end - word_start_pos
-                    }
+                    },
+                    highlight: None,
+                    style: Style::default(),
+                    spans: take(&mut word_spans),
+                    chars: take(&mut word_chars),
+                    link: None,
+                    decoration: None,
+                    leader: false,
                 });
                 
                 out.push(' ');
@@ -70,6 +100,7 @@ pub fn concat_text<'a>(out: &mut String, items: impl Iterator<Item=&'a TextSpan>
             }
             pos = c.offset;
             end = c.pos + x_off + c.width;
+            space_gap = span_gap;
             if c.offset == 0 || !trailing_space {
                 word_end = (span.transform.matrix * Vector2F::new(end, 0.0)).x();
             }
@@ -79,14 +110,31 @@ pub fn concat_text<'a>(out: &mut String, items: impl Iterator<Item=&'a TextSpan>
                 y_max = span.rect.max_y();
                 word_start_pos = (span.transform.matrix * Vector2F::new(c.pos + x_off, 0.0)).x();
                 word_start = false;
+                word_spans.push(span_idx);
             } else {
                 y_min = y_min.min(span.rect.min_y());
                 y_max = y_max.max(span.rect.max_y());
+                if word_spans.last() != Some(&span_idx) {
+                    word_spans.push(span_idx);
+                }
             }
         }
-        trailing_space = span.text[pos..].chars().all(|c| c.is_whitespace());
-
-        out.extend(span.text[pos..].nfkc());
+        if !overstruck {
+            trailing_space = span.text[pos..].chars().all(is_blank);
+            if !trailing_space {
+                let (pos, width) = span.chars.last().map(place).unwrap_or_default();
+                word_chars.push(Char {
+                    offset: out.len() - word_start_idx, pos, width, baseline_shift: shift,
+                    source: span.chars.len().checked_sub(1).map(|last| (span_idx, last)), glyph: None,
+                });
+            }
+            push_text(out, &span.text[pos..], opts);
+        }
+        last_span = Some(span);
+    }
+    // a soft hyphen at the end of the line is where the word was broken, so it becomes visible
+    if !trailing_space && last_span.map(|s| s.text.ends_with('\u{AD}')).unwrap_or(false) {
+        out.push('-');
     }
     words.push(Word {
         text: out[word_start_idx..].into(),
@@ -95,8 +143,759 @@ pub fn concat_text<'a>(out: &mut String, items: impl Iterator<Item=&'a TextSpan>
             y: y_min,
             h: y_max - y_min,
             w: word_end - word_start_pos
-        }
+        },
+        highlight: None,
+        style: Style::default(),
+        spans: word_spans,
+        chars: word_chars,
+        link: None,
+        decoration: None,
+        leader: false,
     });
+
+    set_styles(&mut words, span_styles, opts);
+
+    if opts.bidi && is_rtl(&out[out_start..]) {
+        reorder_rtl(&mut words);
+        out.truncate(out_start);
+        out.push_str(&words.iter().map(|w| w.text.as_str()).join(" "));
+    }
+    mark_leaders(&mut words);
+    if opts.strip_leaders {
+        words = strip_leaders(words);
+    }
     
     words
 }
+
+/// Set [`Word::leader`] on words that are further than twice the text height from the word before,
+/// like the page numbers in a table of contents.
+fn mark_leaders(words: &mut [Word]) {
+    for i in 1 .. words.len() {
+        let (a, b) = (words[i - 1].rect, words[i].rect);
+        // either way round, for right-to-left lines
+        let gap = (b.x - (a.x + a.w)).max(a.x - (b.x + b.w));
+        words[i].leader = gap > 2.0 * a.h.max(b.h);
+    }
+}
+
+/// chars repeated to lead the eye from a label to its value
+const FILLERS: &[char] = &['.', '-', '_', '·', '…'];
+/// shortest run of fillers that counts as a leader, so an ellipsis stays
+const MIN_LEADER: usize = 4;
+
+/// Remove dot leaders, both whole words ("Intro ..... 5", "Intro . . . . 5") and runs inside
+/// a word ("Intro.....5"). The word after a removed leader becomes a [`Word::leader`].
+fn strip_leaders(words: Vec<Word>) -> Vec<Word> {
+    let filler = |w: &Word| {
+        let mut chars = w.text.chars();
+        match chars.next() {
+            Some(c) if FILLERS.contains(&c) => chars.all(|d| d == c),
+            _ => false
+        }
+    };
+    // words that are part of a long enough group of filler words
+    let mut dropped = vec![false; words.len()];
+    let mut i = 0;
+    while i < words.len() {
+        let n = words[i..].iter().take_while(|w| filler(w)).count();
+        let num_chars: usize = words[i .. i + n].iter().map(|w| w.text.chars().count()).sum();
+        if n >= 3 || num_chars >= MIN_LEADER {
+            dropped[i .. i + n].fill(true);
+        }
+        i += n.max(1);
+    }
+
+    let mut out = Vec::with_capacity(words.len());
+    let mut leader = false;
+    for (mut word, dropped) in words.into_iter().zip(dropped) {
+        if dropped {
+            leader = true;
+            continue;
+        }
+        word.leader |= leader;
+        while let Some(run) = filler_run(&word.text) {
+            let head = slice_word(&word, 0 .. run.start);
+            let mut tail = slice_word(&word, run.end .. word.text.len());
+            tail.leader = true;
+            if head.text.len() > 0 {
+                out.push(head);
+            }
+            word = tail;
+        }
+        leader = word.text.len() == 0;
+        if !leader {
+            out.push(word);
+        }
+    }
+    out
+}
+
+/// byte range of the first run of at least [`MIN_LEADER`] equal fillers in `text`
+fn filler_run(text: &str) -> Option<Range<usize>> {
+    let mut start: Option<(usize, char, usize)> = None;
+    for (i, c) in text.char_indices().chain(once((text.len(), ' '))) {
+        match start {
+            Some((_, f, ref mut n)) if c == f => *n += 1,
+            _ => {
+                if let Some((s, _, n)) = start.take() {
+                    if n >= MIN_LEADER {
+                        return Some(s .. i);
+                    }
+                }
+                if FILLERS.contains(&c) {
+                    start = Some((i, c, 1));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The part of `word` in the byte `range` of its text, with the rect narrowed to its chars.
+fn slice_word(word: &Word, range: Range<usize>) -> Word {
+    let chars: Vec<Char> = word.chars.iter()
+        .filter(|c| range.contains(&c.offset))
+        .map(|c| Char { offset: c.offset - range.start, ..*c })
+        .collect();
+    let mut rect = word.rect;
+    if let (Some(first), Some(last)) = (chars.first(), chars.last()) {
+        if first.width > 0.0 || last.width > 0.0 {
+            rect.x = first.pos;
+            rect.w = last.pos + last.width - first.pos;
+        }
+    }
+    Word { text: word.text[range].into(), rect, chars, ..word.clone() }
+}
+
+/// Whether most strongly directional chars in `text` are right-to-left.
+pub(crate) fn is_rtl(text: &str) -> bool {
+    let (mut rtl, mut ltr) = (0, 0);
+    for c in text.chars() {
+        match bidi_class(c) {
+            BidiClass::R | BidiClass::AL => rtl += 1,
+            BidiClass::L => ltr += 1,
+            _ => {}
+        }
+    }
+    rtl > ltr
+}
+
+/// Turn words of a right-to-left line from visual order (as drawn from left to right)
+/// into reading order.
+///
+/// The line is run through the Unicode Bidi Algorithm with a right-to-left paragraph level,
+/// and its runs are put in the order it gives, which turns the drawing order back into the
+/// reading order. Left-to-right runs like numbers or "New York" keep their order. The chars
+/// keep their positions, the rect of a word covers the words its chars came from.
+fn reorder_rtl(words: &mut Vec<Word>) {
+    // the line as drawn, and the word and byte offset in it of every char
+    let mut line = String::new();
+    let mut origin: Vec<Option<(usize, usize)>> = vec![];
+    for (w, word) in words.iter().enumerate() {
+        if w > 0 {
+            line.push(' ');
+            origin.push(None);
+        }
+        for (offset, c) in word.text.char_indices() {
+            line.push(c);
+            origin.extend(std::iter::repeat(Some((w, offset))).take(c.len_utf8()));
+        }
+    }
+
+    let info = BidiInfo::new(&line, Some(Level::rtl()));
+    let para = match info.paragraphs.first() {
+        Some(para) => para,
+        None => return
+    };
+    let (levels, runs) = info.visual_runs(para, para.range.clone());
+    // byte indices into `line` in reading order
+    let mut order: Vec<usize> = vec![];
+    for run in runs {
+        let starts = line[run.clone()].char_indices().map(|(i, _)| run.start + i);
+        if levels[run.start].is_rtl() {
+            order.extend(starts.rev());
+        } else {
+            order.extend(starts);
+        }
+    }
+
+    let mut out: Vec<Word> = vec![];
+    let mut current: Option<Word> = None;
+    for i in order {
+        let (w, offset) = match origin[i] {
+            Some(o) => o,
+            None => {
+                out.extend(current.take());
+                continue;
+            }
+        };
+        let source = &words[w];
+        let c = line[i..].chars().next().unwrap();
+        let word = current.get_or_insert_with(|| Word {
+            text: String::new(),
+            chars: vec![],
+            spans: vec![],
+            leader: false,
+            ..source.clone()
+        });
+        if let Some(ch) = source.chars.iter().find(|ch| ch.offset == offset) {
+            word.chars.push(Char { offset: word.text.len(), ..*ch });
+        }
+        word.text.push(c);
+        word.rect = word.rect.union(source.rect);
+        for &span in source.spans.iter() {
+            if !word.spans.contains(&span) {
+                word.spans.push(span);
+            }
+        }
+    }
+    out.extend(current);
+    *words = out;
+}
+
+/// [`concat_text`] for vertical writing, where the span rects are already rotated.
+///
+/// The glyph positions inside a span run along the wrong axis, so words are split
+/// by the gaps between spans only.
+fn concat_vertical<'a>(out: &mut String, items: impl Iterator<Item=(usize, &'a TextSpan)> + Clone, opts: &Options) -> Vec<Word> {
+    let span_styles = items.clone();
+    let mut words = vec![];
+    let mut word: Option<(usize, RectF, Vec<usize>)> = None;
+    for (span_idx, span) in items {
+        match word {
+            Some((_, ref mut rect, ref mut spans)) if span.rect.min_x() - rect.max_x() < 0.5 * span.font_size => {
+                *rect = rect.union_rect(span.rect);
+                spans.push(span_idx);
+            }
+            _ => {
+                if let Some((start, rect, spans)) = word.take() {
+                    words.push(Word { text: out[start..].into(), rect: rect.into(), highlight: None, style: Style::default(), spans, chars: vec![], link: None, decoration: None, leader: false });
+                    out.push(' ');
+                }
+                word = Some((out.len(), span.rect, vec![span_idx]));
+            }
+        }
+        push_text(out, span.text.trim(), opts);
+    }
+    if let Some((start, rect, spans)) = word {
+        words.push(Word { text: out[start..].into(), rect: rect.into(), highlight: None, style: Style::default(), spans, chars: vec![], link: None, decoration: None, leader: false });
+    }
+    set_styles(&mut words, span_styles, opts);
+    words
+}
+
+/// Text of a line in a monospace font, with the spacing kept.
+///
+/// Every char is put at the column its position corresponds to, counted from `left`
+/// in units of the median char width, so indentation and alignment survive.
+/// The whole line becomes a single word.
+pub fn concat_code<'a>(items: impl Iterator<Item=(usize, &'a TextSpan)> + Clone, left: f32, opts: &Options) -> Vec<Word> {
+    // (left edge, width, text) of every char in page space
+    let mut chars = vec![];
+    let mut rect: Option<RectF> = None;
+    let mut spans = vec![];
+    for (span_idx, span) in items.clone() {
+        let tr_inv = span.transform.matrix.inverse();
+        let x_off = (tr_inv * span.transform.vector).x();
+        let offsets = span.chars.iter().map(|c| c.offset).skip(1).chain(std::iter::once(span.text.len()));
+        for (c, end) in span.chars.iter().zip(offsets) {
+            let x0 = (span.transform.matrix * Vector2F::new(c.pos + x_off, 0.0)).x();
+            let x1 = (span.transform.matrix * Vector2F::new(c.pos + x_off + c.width, 0.0)).x();
+            chars.push((x0, x1 - x0, &span.text[c.offset..end]));
+        }
+        rect = Some(rect.map_or(span.rect, |r| r.union_rect(span.rect)));
+        spans.push(span_idx);
+    }
+    let rect = match rect {
+        Some(r) => r,
+        None => return vec![]
+    };
+
+    let mut widths: Vec<f32> = chars.iter().filter(|c| !c.2.trim().is_empty()).map(|c| c.1).collect();
+    widths.sort_unstable_by(|a, b| a.total_cmp(b));
+    let char_width = match widths.get(widths.len() / 2) {
+        Some(&w) if w > 0.0 => w,
+        _ => return concat_text(&mut String::new(), items, opts)
+    };
+
+    chars.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut text = String::new();
+    let mut column = 0;
+    for (x, _, s) in chars {
+        if s.trim().is_empty() {
+            continue;
+        }
+        let col = ((x - left) / char_width).round().max(0.0) as usize;
+        while column < col {
+            text.push(' ');
+            column += 1;
+        }
+        push_text(&mut text, s, opts);
+        column += 1;
+    }
+
+    vec![Word {
+        text,
+        rect: rect.into(),
+        highlight: None,
+        style: Style::default(),
+        spans,
+        chars: vec![],
+        link: None,
+        decoration: None,
+        leader: false,
+    }]
+}
+
+/// Mark words as bold or italic if all of their spans are.
+fn set_styles<'a>(words: &mut [Word], items: impl Iterator<Item=(usize, &'a TextSpan)>, opts: &Options) {
+    let styles: HashMap<usize, Style> = items
+        .map(|(i, s)| (i, s.font.as_ref().map(|f| opts.styles.font_style(f)).unwrap_or_default()))
+        .collect();
+    for word in words.iter_mut() {
+        let mut spans = word.spans.iter().filter_map(|i| styles.get(i));
+        word.style = match spans.next() {
+            Some(&first) => spans.fold(first, |a, b| Style { bold: a.bold && b.bold, italic: a.italic && b.italic }),
+            None => Style::default(),
+        };
+    }
+}
+
+/// Which spans of a line are raised or lowered against the rest of the line.
+///
+/// A span is shifted if its font is clearly smaller than the median font size of the line
+/// and its center is above (superscript) or below (subscript) the center of the full size text.
+fn baseline_shifts<'a>(items: impl Iterator<Item=(usize, &'a TextSpan)> + Clone) -> HashMap<usize, BaselineShift> {
+    let mut sizes: Vec<f32> = items.clone().map(|(_, s)| s.font_size).collect();
+    sizes.sort_unstable_by(|a, b| a.total_cmp(b));
+    let size = match sizes.get(sizes.len() / 2) {
+        Some(&size) => size,
+        None => return HashMap::new()
+    };
+    let is_small = |s: &TextSpan| s.font_size < 0.85 * size;
+    let center = match avg(items.clone().filter(|(_, s)| !is_small(s)).map(|(_, s)| s.rect.center().y())) {
+        Some(c) => c,
+        None => return HashMap::new()
+    };
+    let line_height = avg(items.clone().filter(|(_, s)| !is_small(s)).map(|(_, s)| s.rect.height())).unwrap_or(size);
+
+    items.filter(|(_, s)| is_small(s))
+        .filter_map(|(i, s)| {
+            // y grows downwards
+            let dy = s.rect.center().y() - center;
+            if dy < -0.15 * line_height {
+                Some((i, BaselineShift::Superscript))
+            } else if dy > 0.1 * line_height {
+                Some((i, BaselineShift::Subscript))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Whether char `i` of `span` repeats the char before it at almost the same position,
+/// as done to fake a bold font. Doubled letters are a full glyph width apart.
+fn is_overstruck(span: &TextSpan, i: usize) -> bool {
+    let (prev, c) = (&span.chars[i - 1], &span.chars[i]);
+    let prev_char = span.text[prev.offset..].chars().next();
+    let char = span.text[c.offset..].chars().next();
+    prev_char == char
+        && !char.map(is_blank).unwrap_or(true)
+        && (c.pos - prev.pos).abs() < 0.2 * prev.width
+}
+
+/// Identifies a font at a given size.
+type FontKey = (usize, u32);
+
+fn font_key(span: &TextSpan) -> FontKey {
+    let font = span.font.as_ref().map(|f| Arc::as_ptr(f) as usize).unwrap_or(0);
+    (font, span.font_size.to_bits())
+}
+
+/// Gap between two chars above which they belong to different words.
+struct WordGaps {
+    /// threshold for all chars of the line
+    default: f32,
+    /// threshold for chars of a given font
+    per_font: HashMap<FontKey, f32>,
+}
+impl WordGaps {
+    fn get(&self, span: &TextSpan) -> f32 {
+        self.per_font.get(&font_key(span)).cloned().unwrap_or(self.default)
+    }
+}
+
+/// Compute the word gap thresholds, both for the whole line and for each font on it.
+///
+/// A gap is attributed to the font of the char to its left.
+fn analyze_word_gap<'a>(items: impl Iterator<Item=(usize, &'a TextSpan)> + Clone) -> WordGaps {
+    let gaps: Vec<(FontKey, f32)> = items.clone()
+        .flat_map(|(_, s)| {
+            let tr_inv = s.transform.matrix.inverse();
+            let pos = (tr_inv * s.transform.vector).x();
+            let key = font_key(s);
+            s.chars.iter()
+                .filter(|c| !is_blank(s.text[c.offset..].chars().next().unwrap()))
+                // (left edge, right edge, font size, font)
+                .map(move |c| (c.pos + pos, c.pos + pos + c.width, s.font_size, key))
+        })
+        .tuple_windows()
+        // skip things that go in reverse
+        .filter(|(a, b)| b.0 > a.0)
+        // compute the distance between the right edge of the left char and the left edge of the right char
+        // and clamp it to a minimum of 0.01 and maximum of half the mean font size
+        .map(|(a, b)| (a.3, (b.0 - a.1).max(0.01).min(0.25 * (a.2 + b.2))))
+        .collect();
+
+    // TJ arrays encode word spaces as position adjustments without any space glyph.
+    // If there is no whitespace at all, the gaps are the only hint, so be less strict.
+    let has_whitespace = items.clone().any(|(_, s)| s.text.chars().any(|c| c.is_whitespace()));
+    let threshold = |font_size: f32, avg_gap: f32| if has_whitespace {
+        // set the threshold at twice the average gap, clamped to half the font size
+        (0.5 * font_size).min(2.0 * avg_gap)
+    } else {
+        // 1.5 times the average gap, clamped between 0.05 and 0.2 times the font size
+        (0.2 * font_size).min(1.5 * avg_gap).max(0.05 * font_size)
+    };
+
+    // compute the average font size of all chars
+    let font_size = avg(items.map(|(_, s)| s.font_size)).unwrap_or(0.0);
+    let default = threshold(font_size, avg(gaps.iter().map(|&(_, g)| g)).unwrap_or(0.0));
+
+    let per_font = gaps.iter()
+        .into_group_map_by(|&&(key, _)| key)
+        .into_iter()
+        .map(|(key, gaps)| {
+            let font_size = f32::from_bits(key.1);
+            (key, threshold(font_size, avg(gaps.iter().map(|&&(_, g)| g)).unwrap()))
+        })
+        .collect();
+
+    WordGaps { default, per_font }
+}
+
+/// Join words that are hyphenated at the end of a line with their second half
+/// at the start of the next line.
+///
+/// Only applies if the next line starts with a lowercase letter. The joined word
+/// keeps the position of its first half.
+pub fn dehyphenate(lines: &mut Vec<Line>) {
+    for i in 1 .. lines.len() {
+        let (before, after) = lines.split_at_mut(i);
+        let (prev, next) = (&mut before[i - 1], &mut after[0]);
+        let (last, first) = match (prev.words.last_mut(), next.words.first()) {
+            (Some(last), Some(first)) => (last, first),
+            _ => continue
+        };
+        if !first.text.chars().next().map(|c| c.is_lowercase()).unwrap_or(false) {
+            continue;
+        }
+        let stem_len = match last.text.strip_suffix(|c| matches!(c, '-' | '\u{AD}')) {
+            Some(stem) if stem.len() > 0 => stem.len(),
+            _ => continue
+        };
+        last.text.truncate(stem_len);
+        last.chars.retain(|c| c.offset < stem_len);
+        last.chars.extend(first.chars.iter().map(|c| Char { offset: c.offset + stem_len, ..*c }));
+        last.text.push_str(&first.text);
+        let first = next.words.remove(0);
+        for span in first.spans {
+            if !last.spans.contains(&span) {
+                last.spans.push(span);
+            }
+        }
+    }
+    lines.retain(|l| l.words.len() > 0);
+}
+
+/// Ligature glyphs and the letters they stand for.
+/// NFKC already splits most of them, but fonts also put them in the private use area.
+pub(crate) const LIGATURES: &[(char, &str)] = &[
+    ('\u{FB00}', "ff"),
+    ('\u{FB01}', "fi"),
+    ('\u{FB02}', "fl"),
+    ('\u{FB03}', "ffi"),
+    ('\u{FB04}', "ffl"),
+    ('\u{FB05}', "st"),
+    ('\u{FB06}', "st"),
+    ('\u{0132}', "IJ"),
+    ('\u{0133}', "ij"),
+];
+
+/// Append the normalized `text` to `out`, expanding ligatures.
+fn push_text(out: &mut String, text: &str, opts: &Options) {
+    match opts.normalization {
+        Normalization::None => expand_ligatures(out, text.chars(), &opts.ligatures),
+        Normalization::NFC => expand_ligatures(out, text.nfc(), &opts.ligatures),
+        Normalization::NFKC => expand_ligatures(out, text.nfkc(), &opts.ligatures),
+    }
+}
+
+fn expand_ligatures(out: &mut String, chars: impl Iterator<Item=char>, ligatures: &HashMap<char, String>) {
+    for c in chars {
+        if is_invisible(c) {
+            continue;
+        }
+        match ligatures.get(&c) {
+            Some(letters) => out.push_str(letters),
+            None => out.push(c),
+        }
+    }
+}
+
+/// Characters that take up no space and are dropped from the text:
+/// soft hyphen, zero width space and byte order mark.
+fn is_invisible(c: char) -> bool {
+    matches!(c, '\u{AD}' | '\u{200B}' | '\u{FEFF}')
+}
+
+/// Whitespace, or a zero width character that separates words just the same.
+fn is_blank(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '\u{200B}' | '\u{FEFF}')
+}
+
+/// UTF-8 text that was decoded as Windows-1252, and what it should have been.
+/// Longer sequences come first, so they are replaced before their prefixes.
+const MOJIBAKE: &[(&str, &str)] = &[
+    ("â€™", "’"),
+    ("â€˜", "‘"),
+    ("â€œ", "“"),
+    ("â€\u{9d}", "”"),
+    ("â€“", "–"),
+    ("â€”", "—"),
+    ("â€¦", "…"),
+    ("â€¢", "•"),
+    ("â‚¬", "€"),
+    ("Ã©", "é"),
+    ("Ã¨", "è"),
+    ("Ã¡", "á"),
+    ("Ã¢", "â"),
+    ("Ã¤", "ä"),
+    ("Ã§", "ç"),
+    ("Ãª", "ê"),
+    ("Ã«", "ë"),
+    ("Ã®", "î"),
+    ("Ã±", "ñ"),
+    ("Ã³", "ó"),
+    ("Ã´", "ô"),
+    ("Ã¶", "ö"),
+    ("Ãº", "ú"),
+    ("Ã¼", "ü"),
+    ("ÃŸ", "ß"),
+    ("Ã„", "Ä"),
+    ("Ã–", "Ö"),
+    ("Ãœ", "Ü"),
+    ("Ã‰", "É"),
+    ("Â\u{a0}", "\u{a0}"),
+    ("Â©", "©"),
+    ("Â®", "®"),
+    ("Â°", "°"),
+    ("Â·", "·"),
+];
+
+/// Repair common mojibake patterns (UTF-8 read as Windows-1252).
+///
+/// This only knows a fixed set of sequences and can misfire on text that
+/// legitimately contains them, so it is not applied by default.
+///
+/// "í" can't be repaired in extracted text: its second byte reads as a soft hyphen,
+/// which is dropped from the text before this sees it.
+pub fn repair_mojibake(text: &str) -> String {
+    let mut out = text.to_string();
+    if !out.contains(|c| matches!(c, 'â' | 'Ã' | 'Â')) {
+        return out;
+    }
+    for &(bad, good) in MOJIBAKE {
+        if out.contains(bad) {
+            out = out.replace(bad, good);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{line, span, word};
+
+    fn texts(words: &[Word]) -> Vec<&str> {
+        words.iter().map(|w| w.text.as_str()).collect()
+    }
+
+    #[test]
+    fn rtl_keeps_ltr_runs_in_order() {
+        // "שלום New York" as drawn from left to right
+        let mut words = line(&["New", "York", "םולש"], 0.0, 0.0).words;
+        reorder_rtl(&mut words);
+        assert_eq!(texts(&words), ["שלום", "New", "York"]);
+    }
+
+    #[test]
+    fn rtl_keeps_char_positions() {
+        let mut words = line(&["םולש"], 0.0, 0.0).words;
+        reorder_rtl(&mut words);
+        let chars = &words[0].chars;
+        assert_eq!(chars.len(), 4);
+        // the first char read is the rightmost one
+        assert_eq!(chars[0].offset, 0);
+        assert_eq!(chars[0].pos, 15.0);
+        assert_eq!(chars[3].pos, 0.0);
+    }
+
+    fn concat(spans: &[TextSpan], opts: &Options) -> Vec<Word> {
+        concat_text(&mut String::new(), spans.iter().enumerate(), opts)
+    }
+
+    #[test]
+    fn word_gap_without_whitespace() {
+        // a TJ array that moves each word a quarter em to the right instead of drawing a space
+        let spans = [span("I", 0.0, 0.0, 10.0), span("am", 7.5, 0.0, 10.0), span("in", 20.0, 0.0, 10.0)];
+        assert_eq!(texts(&concat(&spans, &Options::default())), ["I", "am", "in"]);
+    }
+
+    #[test]
+    fn kerning_does_not_split_words() {
+        let spans = [span("Hel", 0.0, 0.0, 10.0), span("lo", 15.3, 0.0, 10.0), span("World", 27.3, 0.0, 10.0)];
+        assert_eq!(texts(&concat(&spans, &Options::default())), ["Hello", "World"]);
+    }
+
+    #[test]
+    fn mojibake() {
+        assert_eq!(repair_mojibake("donâ€™t"), "don’t");
+        assert_eq!(repair_mojibake("Ã©tÃ© â€“ 5 â‚¬"), "été – 5 €");
+        assert_eq!(repair_mojibake("GrÃ¼ÃŸe"), "Grüße");
+        // correct text is left alone
+        assert_eq!(repair_mojibake("café – Ärger"), "café – Ärger");
+    }
+
+    fn pushed(text: &str, opts: &Options) -> String {
+        let mut out = String::new();
+        push_text(&mut out, text, opts);
+        out
+    }
+
+    #[test]
+    fn ligatures() {
+        let opts = Options::default().normalization(Normalization::None);
+        assert_eq!(pushed("\u{FB01}nd e\u{FB00}ort", &opts), "find effort");
+        // a font with its ligatures in the private use area
+        let opts = opts.ligature('\u{F04E}', "ffi");
+        assert_eq!(pushed("o\u{F04E}ce", &opts), "office");
+    }
+
+    #[test]
+    fn dehyphenate_wrapped_words() {
+        let mut lines = vec![line(&["an", "exam-"], 0.0, 0.0), line(&["ple", "text"], 0.0, 20.0)];
+        dehyphenate(&mut lines);
+        assert_eq!(texts(&lines[0].words), ["an", "example"]);
+        assert_eq!(texts(&lines[1].words), ["text"]);
+        let offsets: Vec<usize> = lines[0].words[1].chars.iter().map(|c| c.offset).collect();
+        assert_eq!(offsets, [0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn dehyphenate_keeps_names_and_drops_empty_lines() {
+        let mut lines = vec![line(&["Jean-"], 0.0, 0.0), line(&["Paul"], 0.0, 20.0)];
+        dehyphenate(&mut lines);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(texts(&lines[0].words), ["Jean-"]);
+
+        let mut lines = vec![line(&["co-"], 0.0, 0.0), line(&["operate"], 0.0, 20.0)];
+        dehyphenate(&mut lines);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(texts(&lines[0].words), ["cooperate"]);
+    }
+
+    #[test]
+    fn normalization() {
+        let decomposed = "Cafe\u{301} x\u{B2}";
+        let with = |n| pushed(decomposed, &Options::default().normalization(n));
+        assert_eq!(with(Normalization::None), decomposed);
+        assert_eq!(with(Normalization::NFC), "Caf\u{E9} x\u{B2}");
+        assert_eq!(with(Normalization::NFKC), "Caf\u{E9} x2");
+    }
+
+    #[test]
+    fn invisible_chars() {
+        assert_eq!(pushed("soft\u{AD}ware\u{200B} \u{FEFF}zero", &Options::default()), "software zero");
+        assert_eq!(texts(&concat(&[span("soft\u{AD}ware", 0.0, 0.0, 10.0)], &Options::default())), ["software"]);
+    }
+
+    #[test]
+    fn soft_hyphen_at_line_end_shows() {
+        assert_eq!(texts(&concat(&[span("exam\u{AD}", 0.0, 0.0, 10.0)], &Options::default())), ["exam-"]);
+    }
+
+    #[test]
+    fn word_gap_per_font() {
+        // small text with quarter em word gaps, followed by a title with much wider ones
+        let spans = [
+            span("ab", 0.0, 0.0, 10.0), span("cd", 12.5, 0.0, 10.0),
+            span("EF", 40.0, 0.0, 20.0), span("GH", 65.0, 0.0, 20.0),
+        ];
+        let gaps = analyze_word_gap(spans.iter().enumerate());
+        assert!(gaps.get(&spans[0]) < gaps.get(&spans[2]));
+        // a single threshold for the whole line would join "ab" and "cd"
+        assert!(gaps.default > 2.5);
+        assert_eq!(texts(&concat(&spans, &Options::default())), ["ab", "cd", "EF", "GH"]);
+    }
+
+    #[test]
+    fn overstruck_chars_collapse() {
+        // fake bold: every glyph drawn twice, a little to the right
+        let mut fake_bold = span("HHii", 0.0, 0.0, 10.0);
+        for (c, pos) in fake_bold.chars.iter_mut().zip([0.0, 0.3, 5.0, 5.3]) {
+            c.pos = pos;
+        }
+        assert!(is_overstruck(&fake_bold, 1));
+        assert!(!is_overstruck(&fake_bold, 2));
+        assert_eq!(texts(&concat(&[fake_bold], &Options::default())), ["Hi"]);
+
+        // a doubled letter is a full glyph apart
+        let doubled = span("all", 0.0, 0.0, 10.0);
+        assert!(!is_overstruck(&doubled, 2));
+    }
+
+    #[test]
+    fn superscripts_and_subscripts() {
+        let spans = [
+            span("x", 0.0, 0.0, 10.0), span("2", 5.0, -2.0, 6.0),
+            span("H", 20.0, 0.0, 10.0), span("2", 25.0, 8.0, 6.0), span("O", 28.0, 0.0, 10.0),
+        ];
+        let shifts = baseline_shifts(spans.iter().enumerate());
+        assert_eq!(shifts.get(&1), Some(&BaselineShift::Superscript));
+        assert_eq!(shifts.get(&3), Some(&BaselineShift::Subscript));
+        assert_eq!(shifts.get(&0), None);
+
+        let words = concat(&spans, &Options::default());
+        let shifts: Vec<BaselineShift> = words[0].chars.iter().map(|c| c.baseline_shift).collect();
+        assert_eq!(shifts, [BaselineShift::Normal, BaselineShift::Superscript]);
+    }
+
+    #[test]
+    fn wide_gaps_are_leaders() {
+        let mut words = line(&["Intro", "to", "it"], 0.0, 0.0).words;
+        words.push(word("5", 200.0, 0.0));
+        mark_leaders(&mut words);
+        assert_eq!(words.iter().map(|w| w.leader).collect::<Vec<_>>(), [false, false, false, true]);
+    }
+
+    #[test]
+    fn dot_leaders() {
+        assert_eq!(filler_run("Intro.....5"), Some(5 .. 10));
+        assert_eq!(filler_run("Wait..."), None);
+        assert_eq!(filler_run("a-b-c"), None);
+
+        let words = strip_leaders(line(&["Intro", ".....", "5", "Wait..."], 0.0, 0.0).words);
+        assert_eq!(texts(&words), ["Intro", "5", "Wait..."]);
+        assert_eq!(words.iter().map(|w| w.leader).collect::<Vec<_>>(), [false, true, false]);
+
+        let words = strip_leaders(line(&["Chapter", "one.......12"], 0.0, 0.0).words);
+        assert_eq!(texts(&words), ["Chapter", "one", "12"]);
+        assert!(words[2].leader);
+        assert_eq!(words[2].rect.x, 40.0 + 5.0 * 10.0);
+        assert_eq!(words[2].chars[0].offset, 0);
+    }
+}
@@ -3,21 +3,22 @@ use pathfinder_geometry::{
     vector::Vector2F,
     rect::RectF
 };
-#[cfg(feature="ocr")]
-use tesseract_plumbing::Text;
 
-use std::collections::BTreeSet;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
 use std::iter::once;
 use std::sync::Arc;
 use itertools::{Itertools};
 use ordered_float::NotNan;
-use crate::entry::{Flow, Line, Run, RunType, Word};
-use crate::util::{is_number, avg, CellContent};
-use crate::text::{concat_text};
+use crate::entry::{Align, Flow, Line, Run, RunType, Word};
+use crate::util::{is_number, looks_like_page_number, avg, CellContent, Deadline};
+use crate::text::{concat_text, concat_code, dehyphenate, is_rtl};
+use crate::style::{StyleClassifier, RunStyle};
+use crate::{Options, ParagraphStyle};
 use std::mem::take;
 use table::Table;
 
-pub fn build(spans: &[TextSpan], bbox: RectF, lines: &[[f32; 4]]) -> Node {
+pub fn build(spans: &[TextSpan], bbox: RectF, lines: &[([f32; 4], f32)], opts: &Options, classes: &ClassCache, deadline: &Deadline) -> Node {
     if spans.len() == 0 {
         return Node::singleton(&[]);
     }
@@ -27,12 +28,16 @@ pub fn build(spans: &[TextSpan], bbox: RectF, lines: &[[f32; 4]]) -> Node {
     
     let avg_font_size = avg(spans.iter().map(|s| s.font_size)).unwrap();
     let probaby_header = |boxes: &[(RectF, usize)]| {
-        let class = classify(boxes.iter().filter_map(|&(_, i)| spans.get(i)));
+        let class = classes.classify(boxes.iter().map(|&(_, i)| i), spans, &opts.styles);
         if matches!(class, Class::Header | Class::Number) {
             return true;
         }
-        let f = avg(boxes.iter().filter_map(|&(_, i)| spans.get(i)).map(|s| s.font_size)).unwrap();
-        f > avg_font_size
+        let text = boxes.iter().filter_map(|&(_, i)| spans.get(i)).map(|s| s.text.as_str()).join(" ");
+        if looks_like_page_number(&text) {
+            return true;
+        }
+        let f = avg(boxes.iter().filter_map(|&(_, i)| spans.get(i)).map(|s| s.font_size));
+        f.map_or(false, |f| f > avg_font_size)
     };
     let probably_footer = |boxes: &mut [(RectF, usize)]| {
         sort_x(boxes);
@@ -43,35 +48,110 @@ pub fn build(spans: &[TextSpan], bbox: RectF, lines: &[[f32; 4]]) -> Node {
         count == x_gaps.len() + 1
     };
 
-    sort_y(boxes);
-    let (top, bottom) = top_bottom_gap(boxes, bbox);
-    if let Some(bottom) = bottom {
-        if probably_footer(&mut boxes[bottom..]) {
-            boxes = &mut boxes[..bottom];
+    let lines = analyze_lines(&straighten(&cell_borders(lines), opts.line_tolerance), opts.line_merge_threshold);
+    if opts.strip_header_footer {
+        sort_y(boxes);
+        let (top, bottom) = top_bottom_gap(boxes, bbox, opts.header_band, opts.footer_band);
+        if let Some(bottom) = bottom {
+            if probably_footer(&mut boxes[bottom..]) {
+                boxes = &mut boxes[..bottom];
+            }
         }
-    }
-    if let Some(top) = top {
-        if probaby_header(&mut boxes[..top]) {
-            boxes = &mut boxes[top..];
+        if let Some(top) = top {
+            if probaby_header(&mut boxes[..top]) {
+                boxes = &mut boxes[top..];
+            }
         }
-    }
-    sort_x(boxes);
-    let (left, right) = left_right_gap(boxes, bbox);
-    if let Some(right) = right {
-        if probaby_header(&boxes[right..]) {
-            boxes = &mut boxes[..right];
+        sort_x(boxes);
+        let (left, right) = left_right_gap(boxes, bbox, opts.left_band, opts.right_band);
+        if let Some(right) = right {
+            if probaby_header(&boxes[right..]) {
+                boxes = &mut boxes[..right];
+            }
+        }
+        if let Some(left) = left {
+            if probaby_header(&boxes[..left]) {
+                boxes = &mut boxes[left..];
+            }
         }
     }
-    if let Some(left) = left {
-        if probaby_header(&boxes[..left]) {
-            boxes = &mut boxes[left..];
+
+    sort_y(boxes);
+    if let Some((y, idx)) = footnote_rule(boxes, spans, &lines, bbox, avg_font_size) {
+        let (body, notes) = boxes.split_at_mut(idx);
+        let notes = Node::Grid {
+            x: vec![],
+            y: vec![],
+            cells: vec![split(notes, spans, &lines, opts, deadline)],
+            tag: NodeTag::Footnote,
+        };
+        return Node::Grid {
+            x: vec![],
+            y: vec![y],
+            cells: vec![split_ruled(body, spans, &lines, opts, deadline), notes],
+            tag: NodeTag::Complex,
+        };
+    }
+    split_ruled(boxes, &spans, &lines, opts, deadline)
+}
+
+/// Look for a horizontal rule in the lower half of the page with only small text below it,
+/// which separates the footnotes from the body.
+///
+/// `boxes` have to be sorted by y. Returns the position of the rule and the index of the first footnote box.
+fn footnote_rule(boxes: &[(RectF, usize)], spans: &[TextSpan], lines: &Lines, bbox: RectF, avg_font_size: f32) -> Option<(f32, usize)> {
+    let middle = bbox.min_y() + 0.5 * bbox.height();
+    for &(start, end) in lines.hlines.iter().filter(|&&(start, _)| start > middle) {
+        let idx = match boxes.iter().position(|(r, _)| r.min_y() > end) {
+            Some(idx) => idx,
+            None => continue
+        };
+        // the rule must not cut through text
+        if boxes[..idx].iter().any(|(r, _)| r.max_y() > start) || idx == 0 {
+            continue;
+        }
+        let notes = &boxes[idx..];
+        let size = avg(notes.iter().filter_map(|&(_, i)| spans.get(i)).map(|s| s.font_size));
+        if size.map_or(false, |size| size < 0.9 * avg_font_size) {
+            return Some((0.5 * (start + end), idx));
         }
     }
-    let lines = analyze_lines(lines);
-    split(boxes, &spans, &lines)
+    None
+}
+
+/// The lines that can be cell borders.
+///
+/// Tables are ruled with thin lines. Lines that are clearly thicker than the typical line
+/// on the page (at least 2 units and three times the median width) divide sections instead,
+/// and would only confuse the table detection.
+fn cell_borders(lines: &[([f32; 4], f32)]) -> Vec<[f32; 4]> {
+    let mut widths: Vec<f32> = lines.iter().map(|&(_, w)| w).collect();
+    widths.sort_unstable_by(|a, b| a.total_cmp(b));
+    let median = widths.get(widths.len() / 2).cloned().unwrap_or(0.0);
+    let max_width = (3.0 * median).max(2.0);
+    lines.iter().filter(|&&(_, w)| w <= max_width).map(|&(line, _)| line).collect()
+}
+
+/// Snap lines that are almost horizontal or vertical, as on slightly skewed pages,
+/// to their average y or x. A line is almost horizontal if it rises by less than
+/// `tolerance` times its length (and the same for vertical lines).
+fn straighten(lines: &[[f32; 4]], tolerance: f32) -> Vec<[f32; 4]> {
+    lines.iter().map(|&[x1, y1, x2, y2]| {
+        let (dx, dy) = ((x2 - x1).abs(), (y2 - y1).abs());
+        if dy <= tolerance * dx {
+            let y = 0.5 * (y1 + y2);
+            [x1, y, x2, y]
+        } else if dx <= tolerance * dy {
+            let x = 0.5 * (x1 + x2);
+            [x, y1, x, y2]
+        } else {
+            [x1, y1, x2, y2]
+        }
+    }).collect()
 }
 
-fn analyze_lines(lines: &[[f32; 4]]) -> Lines {
+/// Collect the ruling lines. Parallel lines closer than `merge_threshold` are merged.
+fn analyze_lines(lines: &[[f32; 4]], merge_threshold: f32) -> Lines {
     let mut hlines = BTreeSet::new();
     let mut vlines = BTreeSet::new();
 
@@ -83,27 +163,8 @@ fn analyze_lines(lines: &[[f32; 4]]) -> Lines {
         }
     }
 
-    fn dedup(lines: impl Iterator<Item=NotNan<f32>>) -> Vec<(f32, f32)> {
-        let threshold = 10.0;
-        let mut out = vec![];
-        let mut lines = lines.map(|f| *f).peekable();
-        while let Some(start) = lines.next() {
-            let mut last = start;
-            while let Some(&p) = lines.peek() {
-                if last + threshold > p {
-                    last = p;
-                    lines.next();
-                } else {
-                    break;
-                }
-            }
-            out.push((start, last));
-        }
-        out
-    }
-
-    let hlines = dedup(hlines.iter().cloned());
-    let vlines = dedup(vlines.iter().cloned());
+    let hlines = dedup(hlines.iter().cloned(), merge_threshold);
+    let vlines = dedup(vlines.iter().cloned(), merge_threshold);
 
     let mut line_grid = vec![false; vlines.len() * hlines.len()];
     for &[x1, y1, x2, y2] in lines {
@@ -128,13 +189,255 @@ fn analyze_lines(lines: &[[f32; 4]]) -> Lines {
     //println!("hlines: {:?}", hlines);
     //println!("vlines: {:?}", vlines);
 
-    Lines { hlines, vlines, line_grid }
+    let grid = ruled_grid(lines);
+
+    Lines { hlines, vlines, line_grid, grid }
+}
+
+/// Merge runs of sorted positions that are less than `threshold` apart into `(first, last)` ranges.
+fn dedup(lines: impl Iterator<Item=NotNan<f32>>, threshold: f32) -> Vec<(f32, f32)> {
+    let mut out = vec![];
+    let mut lines = lines.map(|f| *f).peekable();
+    while let Some(start) = lines.next() {
+        let mut last = start;
+        while let Some(&p) = lines.peek() {
+            if last + threshold > p {
+                last = p;
+                lines.next();
+            } else {
+                break;
+            }
+        }
+        out.push((start, last));
+    }
+    out
 }
 
 pub struct Lines {
     hlines: Vec<(f32, f32)>,
     vlines: Vec<(f32, f32)>,
     line_grid: Vec<bool>,
+    grid: Option<RuledGrid>,
+}
+
+/// A table whose cells are completely outlined by ruling lines.
+struct RuledGrid {
+    /// positions of the vertical lines, left to right
+    xs: Vec<f32>,
+    /// positions of the horizontal lines, top to bottom
+    ys: Vec<f32>,
+    /// vertical segments as (x, y_start, y_end)
+    vsegments: Vec<(f32, f32, f32)>,
+}
+impl RuledGrid {
+    fn contains(&self, r: &RectF) -> bool {
+        let c = r.center();
+        self.xs[0] <= c.x() && c.x() <= *self.xs.last().unwrap() &&
+        self.ys[0] <= c.y() && c.y() <= *self.ys.last().unwrap()
+    }
+    /// whether there is a vertical line at `x` from `y0` to `y1`
+    fn has_vline(&self, x: f32, y0: f32, y1: f32) -> bool {
+        covers(&self.vsegments, x, y0, y1)
+    }
+}
+
+/// tolerance for ruling lines that should meet
+const RULE_TOLERANCE: f32 = 2.0;
+
+/// whether the union of `segments` at `pos` covers `from .. to`
+fn covers(segments: &[(f32, f32, f32)], pos: f32, from: f32, to: f32) -> bool {
+    let mut parts: Vec<(f32, f32)> = segments.iter()
+        .filter(|&&(p, _, _)| (p - pos).abs() <= RULE_TOLERANCE)
+        .map(|&(_, a, b)| (a, b))
+        .collect();
+    parts.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut reached = from + RULE_TOLERANCE;
+    for (a, b) in parts {
+        if a > reached {
+            break;
+        }
+        reached = reached.max(b);
+    }
+    reached >= to - RULE_TOLERANCE
+}
+
+/// Look for a grid of at least 2x2 cells that is completely outlined by lines.
+///
+/// The vertical lines sharing the same extent form the columns (the largest such group wins).
+/// Every horizontal line within that extent that spans all columns is a row boundary.
+fn ruled_grid(lines: &[[f32; 4]]) -> Option<RuledGrid> {
+    let mut hsegments = vec![];
+    let mut vsegments = vec![];
+    for &[x1, y1, x2, y2] in lines {
+        if x1 == x2 {
+            vsegments.push((x1, y1.min(y2), y1.max(y2)));
+        } else if y1 == y2 {
+            hsegments.push((y1, x1.min(x2), x1.max(x2)));
+        }
+    }
+
+    // merge the vertical segments into lines with their extent
+    vsegments.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    let mut vlines: Vec<(f32, f32, f32)> = vec![];
+    for &(x, a, b) in vsegments.iter() {
+        match vlines.last_mut() {
+            Some(l) if (l.0 - x).abs() <= RULE_TOLERANCE && a <= l.2 + RULE_TOLERANCE => l.2 = l.2.max(b),
+            _ => vlines.push((x, a, b))
+        }
+    }
+
+    let same_extent = |a: &(f32, f32, f32), b: &(f32, f32, f32)| {
+        (a.1 - b.1).abs() <= RULE_TOLERANCE && (a.2 - b.2).abs() <= RULE_TOLERANCE
+    };
+    let columns: Vec<(f32, f32, f32)> = vlines.iter()
+        .map(|l| vlines.iter().filter(|m| same_extent(l, m)).cloned().collect::<Vec<_>>())
+        .max_by_key(|group| group.len())?;
+    if columns.len() < 3 {
+        return None;
+    }
+    let (_, top, bottom) = columns[0];
+    let xs: Vec<f32> = columns.iter().map(|l| l.0).collect();
+    let (left, right) = (xs[0], *xs.last().unwrap());
+
+    let mut ys: Vec<f32> = vec![];
+    let mut candidates: Vec<f32> = hsegments.iter()
+        .map(|s| s.0)
+        .filter(|&y| top - RULE_TOLERANCE <= y && y <= bottom + RULE_TOLERANCE)
+        .collect();
+    candidates.sort_by(|a, b| a.total_cmp(b));
+    for y in candidates {
+        if ys.last().map(|&l| y - l <= RULE_TOLERANCE).unwrap_or(false) {
+            continue;
+        }
+        if covers(&hsegments, y, left, right) {
+            ys.push(y);
+        }
+    }
+    if ys.len() < 3 || (ys[0] - top).abs() > RULE_TOLERANCE || (ys[ys.len() - 1] - bottom).abs() > RULE_TOLERANCE {
+        return None;
+    }
+
+    Some(RuledGrid { xs, ys, vsegments })
+}
+
+/// Build a table from the ruled grid (if there is one) and split the text around it.
+fn split_ruled(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines: &Lines, opts: &Options, deadline: &Deadline) -> Node {
+    let grid = match lines.grid {
+        Some(ref grid) if opts.detect_tables => grid,
+        _ => return split(boxes, spans, lines, opts, deadline)
+    };
+
+    let (grid_x0, grid_x1) = (grid.xs[0], grid.xs[grid.xs.len() - 1]);
+    let mut inside = vec![];
+    let mut above = vec![];
+    let mut below = vec![];
+    // next to the table, like the other column of the page
+    let mut left = vec![];
+    let mut right = vec![];
+    let mid = 0.5 * (grid.ys[0] + grid.ys[grid.ys.len() - 1]);
+    for &b in boxes.iter() {
+        if grid.contains(&b.0) {
+            inside.push(b);
+        } else if b.0.max_x() <= grid_x0 {
+            left.push(b);
+        } else if b.0.min_x() >= grid_x1 {
+            right.push(b);
+        } else if b.0.center().y() < mid {
+            above.push(b);
+        } else {
+            below.push(b);
+        }
+    }
+    if inside.len() == 0 {
+        return split(boxes, spans, lines, opts, deadline);
+    }
+
+    let num_cols = grid.xs.len() - 1;
+    let num_rows = grid.ys.len() - 1;
+
+    // cells, merged horizontally where the separating line is missing
+    let mut cells: Vec<TableCell> = vec![];
+    for row in 0 .. num_rows {
+        let (y0, y1) = (grid.ys[row], grid.ys[row + 1]);
+        let mut col = 0;
+        while col < num_cols {
+            let mut colspan = 1;
+            while col + colspan < num_cols && !grid.has_vline(grid.xs[col + colspan], y0, y1) {
+                colspan += 1;
+            }
+            cells.push(TableCell {
+                indices: vec![],
+                row: row as u32,
+                col: col as u32,
+                rowspan: 1,
+                colspan: colspan as u32,
+            });
+            col += colspan;
+        }
+    }
+
+    inside.sort_by(|a, b| a.0.min_y().total_cmp(&b.0.min_y()).then(a.0.min_x().total_cmp(&b.0.min_x())));
+    for &(r, i) in inside.iter() {
+        let c = r.center();
+        let col = grid.xs.iter().filter(|&&x| x < c.x()).count().saturating_sub(1).min(num_cols - 1) as u32;
+        let row = grid.ys.iter().filter(|&&y| y < c.y()).count().saturating_sub(1).min(num_rows - 1) as u32;
+        if let Some(cell) = cells.iter_mut().find(|cell| cell.row == row && cell.col <= col && col < cell.col + cell.colspan) {
+            cell.indices.push(i);
+        }
+    }
+
+    let header_rows = header_rows(&cells, num_cols as u32, spans, &opts.styles);
+    let alignment = column_alignment(&cells, num_cols as u32, spans);
+    let mut table: Table<TableCell> = Table::empty(num_rows as u32, num_cols as u32);
+    for cell in cells {
+        let (row, col, rowspan, colspan) = (cell.row, cell.col, cell.rowspan, cell.colspan);
+        table.set_cell(cell, row, col, rowspan, colspan);
+    }
+
+    let mut parts = vec![];
+    let mut y = vec![];
+    if above.len() > 0 {
+        parts.push(split(&mut above, spans, lines, opts, deadline));
+        y.push(grid.ys[0]);
+    }
+    parts.push(Node::Table { table, header_rows, alignment });
+    if below.len() > 0 {
+        y.push(grid.ys[grid.ys.len() - 1]);
+        parts.push(split(&mut below, spans, lines, opts, deadline));
+    }
+    let middle = if parts.len() == 1 {
+        parts.pop().unwrap()
+    } else {
+        Node::Grid {
+            x: vec![],
+            y,
+            cells: parts,
+            tag: NodeTag::Complex,
+        }
+    };
+    if left.len() == 0 && right.len() == 0 {
+        return middle;
+    }
+
+    // the columns left and right of the table are laid out on their own
+    let mut x = vec![];
+    let mut columns = vec![];
+    if left.len() > 0 {
+        columns.push(split(&mut left, spans, lines, opts, deadline));
+        x.push(grid_x0);
+    }
+    columns.push(middle);
+    if right.len() > 0 {
+        x.push(grid_x1);
+        columns.push(split(&mut right, spans, lines, opts, deadline));
+    }
+    Node::Grid {
+        x,
+        y: vec![],
+        cells: columns,
+        tag: NodeTag::Complex,
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -180,7 +483,26 @@ impl Span {
     }
 }
 
-pub fn split2(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines_info: &Lines) -> Node {
+/// The first and last of the sorted `columns` that `x` overlaps.
+fn column_range(columns: &[Span], x: Span) -> (usize, usize) {
+    let mut cols = columns.iter().enumerate()
+        .filter(|&(_, &x2)| x.intersect(x2).is_some())
+        .map(|(i, _)| i);
+
+    match cols.next() {
+        Some(first) => (first, cols.last().unwrap_or(first)),
+        None => {
+            // in the gap between two columns: snap to the nearest one
+            let nearest = columns.iter().enumerate()
+                .min_by_key(|&(_, c)| (c.start - x.end).max(x.start - c.end))
+                .map(|(i, _)| i)
+                .unwrap_or(0);
+            (nearest, nearest)
+        }
+    }
+}
+
+pub fn split2(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines_info: &Lines, opts: &Options) -> Node {
     use std::mem::replace;
 
     #[derive(Debug)]
@@ -214,7 +536,7 @@ pub fn split2(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines_info: &Lin
         }
         line.push((x, items));
 
-        let f = avg(boxes.iter().filter_map(|&(_, i)| spans.get(i)).map(|s| s.font_size)).unwrap();
+        let f = avg(boxes.iter().filter_map(|&(_, i)| spans.get(i)).map(|s| s.font_size)).unwrap_or(0.0);
 
         let max_gap = line.iter().tuple_windows().map(|(l, r)| r.0.start - l.0.end).max();
         let tag = match max_gap {
@@ -272,16 +594,22 @@ pub fn split2(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines_info: &Lin
                 }
             }
         }
-        let avg_vgap = avg(lines.iter().map(|(_, y, _)| y).tuple_windows().map(|(a, b)| *(b.start - a.end)));
+        let mut vgaps: Vec<f32> = lines.iter().map(|(_, y, _)| y).tuple_windows().map(|(a, b)| *(b.start - a.end)).collect();
+        vgaps.sort_unstable_by(|a, b| a.total_cmp(b));
 
         columns.sort_by_key(|s| s.start);
 
         let mut buf = String::new();
 
-        let d_threshold = avg_vgap.unwrap_or(0.0);
+        // Lines of a wrapped cell are closer together than rows. Comparing against the average
+        // gap would merge about half the rows of a uniform table, so only lines that are much
+        // closer than the typical (median) gap are combined.
+        let d_threshold = vgaps.get(vgaps.len() / 2).map(|&g| 0.5 * g).unwrap_or(0.0);
         let mut prev_end = None;
 
-        let mut table: Table<Vec<usize>> = Table::empty(lines.len() as u32, columns.len() as u32);
+        let mut cells: Vec<TableCell> = vec![];
+        // vertical extent of each row
+        let mut rows: Vec<Span> = vec![];
 
         let mut row = 0;
         for (_, span, line) in lines {
@@ -295,32 +623,50 @@ pub fn split2(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines_info: &Lin
                 }
             }).unwrap_or(false);
 
-            if !combine {
+            if !combine && prev_end.is_some() {
                 row += 1;
             }
+            match rows.get_mut(row as usize) {
+                Some(r) => r.end = r.end.max(span.end),
+                None => rows.push(*span),
+            }
 
             for &(x, ref parts) in line {
-                let mut cols = columns.iter().enumerate()
-                    .filter(|&(_, &x2)| x.intersect(x2).is_some())
-                    .map(|(i, _)| i);
+                let (first_col, last_col) = column_range(&columns, x);
 
-                let first_col = cols.next().unwrap();
-                let last_col = cols.last().unwrap_or(first_col);
-
-                if let Some(cell) = combine.then(|| table.get_cell_value_mut(row, first_col as u32)).flatten() {
+                let prev_cell = if combine {
+                    cells.iter_mut().find(|c| c.row == row && c.col == first_col as u32)
+                } else {
+                    None
+                };
+                if let Some(cell) = prev_cell {
                     // append to previous line
-                    cell.extend_from_slice(parts);
+                    cell.indices.extend_from_slice(parts);
                 } else {
-                    let colspan = (last_col - first_col) as u32 + 1;
-                    let rowspan = 1;
-                    table.set_cell(parts.clone(), row, first_col as u32, rowspan, colspan);
+                    cells.push(TableCell {
+                        indices: parts.clone(),
+                        row,
+                        col: first_col as u32,
+                        rowspan: 1,
+                        colspan: (last_col - first_col) as u32 + 1,
+                    });
                 }
                 col = last_col + 1;
             }
             prev_end = Some(span.end);
         }
+        merge_split_cells(&mut cells, spans, lines_info);
+        infer_rowspans(&mut cells, &rows, spans);
+        let header_rows = header_rows(&cells, columns.len() as u32, spans, &opts.styles);
+        let alignment = column_alignment(&cells, columns.len() as u32, spans);
+
+        let mut table: Table<TableCell> = Table::empty(rows.len() as u32, columns.len() as u32);
+        for cell in cells {
+            let (row, col, rowspan, colspan) = (cell.row, cell.col, cell.rowspan, cell.colspan);
+            table.set_cell(cell, row, col, rowspan, colspan);
+        }
         let y = Span { start: lines[0].1.start, end: lines.last().unwrap().1.end };
-        vparts.push((y, Node::Table { table }));
+        vparts.push((y, Node::Table { table, header_rows, alignment }));
     }
     for &(_, y, ref line) in &lines[start..] {
         vparts.push((y, Node::Final { indices: line.iter().flat_map(|(_, indices)| indices.iter().cloned()).collect() }));
@@ -339,21 +685,180 @@ pub fn split2(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines_info: &Lin
     }
 }
 
+/// Merge neighboring cells of a row that are really one value broken up by a wide space.
+///
+/// Two cells in columns `c` and `c+1` are merged if there is no vertical ruling line
+/// between them and no other row has separate cells in those two columns.
+fn merge_split_cells(cells: &mut Vec<TableCell>, spans: &[TextSpan], lines_info: &Lines) {
+    let x_range = |cell: &TableCell| {
+        cell.indices.iter().filter_map(|&i| spans.get(i))
+            .map(|s| (s.rect.min_x(), s.rect.max_x()))
+            .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1)))
+    };
+
+    let mut i = 0;
+    while i < cells.len() {
+        let (row, end_col) = (cells[i].row, cells[i].col + cells[i].colspan);
+        let j = match cells.iter().position(|c| c.row == row && c.col == end_col) {
+            Some(j) => j,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+
+        // another row uses both columns separately
+        let separate_elsewhere = cells.iter().any(|a| a.row != row && a.col + a.colspan == end_col &&
+            cells.iter().any(|b| b.row == a.row && b.col == end_col)
+        );
+        let ruled = match (x_range(&cells[i]), x_range(&cells[j])) {
+            (Some((_, left)), Some((right, _))) => lines_info.vlines.iter().any(|&(a, b)| left <= b && a <= right),
+            _ => true
+        };
+        if separate_elsewhere || ruled {
+            i += 1;
+            continue;
+        }
+
+        // the merged cell may now have another neighbor, so `i` stays the same
+        let right = cells.remove(j);
+        if j < i {
+            i -= 1;
+        }
+        cells[i].colspan += right.colspan;
+        cells[i].indices.extend(right.indices);
+    }
+}
+
+/// Extend cells downwards over empty positions that their text reaches into.
+///
+/// A label that spans several rows (common in financial tables) otherwise leaves
+/// empty cells below it.
+fn infer_rowspans(cells: &mut [TableCell], rows: &[Span], spans: &[TextSpan]) {
+    let num_rows = rows.len() as u32;
+    for i in 0 .. cells.len() {
+        let bottom = match cells[i].indices.iter().filter_map(|&j| spans.get(j)).map(|s| s.rect.max_y()).reduce(f32::max) {
+            Some(b) => b,
+            None => continue
+        };
+        let TableCell { row, col, colspan, mut rowspan, .. } = cells[i];
+        while row + rowspan < num_rows {
+            let next = row + rowspan;
+            let r = rows[next as usize];
+            let occupied = cells.iter().any(|c|
+                c.row <= next && next < c.row + c.rowspan &&
+                c.col < col + colspan && col < c.col + c.colspan
+            );
+            // the cell has to reach past the middle of the next row
+            if occupied || bottom < 0.5 * (r.start + r.end).into_inner() {
+                break;
+            }
+            rowspan += 1;
+        }
+        cells[i].rowspan = rowspan;
+    }
+}
+
+/// Alignment of each column, judged by which edge of the cells lines up.
+///
+/// If both or neither edge line up, columns of numbers are taken as right aligned
+/// and everything else as left aligned.
+fn column_alignment(cells: &[TableCell], num_cols: u32, spans: &[TextSpan]) -> Vec<Align> {
+    let tolerance = 0.3 * avg(spans.iter().map(|s| s.rect.height())).unwrap_or(0.0);
+    let spread = |values: &[f32]| {
+        let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        max - min
+    };
+
+    (0 .. num_cols).map(|col| {
+        let mut left = vec![];
+        let mut right = vec![];
+        let mut numeric = true;
+        for cell in cells.iter().filter(|c| c.col == col && c.colspan == 1) {
+            let mut cell_spans = cell.indices.iter().filter_map(|&i| spans.get(i)).peekable();
+            if cell_spans.peek().is_none() {
+                continue;
+            }
+            let mut text = String::new();
+            let mut min_x = f32::INFINITY;
+            let mut max_x = f32::NEG_INFINITY;
+            for s in cell_spans {
+                text.push_str(&s.text);
+                min_x = min_x.min(s.rect.min_x());
+                max_x = max_x.max(s.rect.max_x());
+            }
+            left.push(min_x);
+            right.push(max_x);
+            numeric &= is_number(&text.replace(|c: char| c.is_whitespace() || ",.-+%$()".contains(c), ""));
+        }
+        if left.len() == 0 {
+            return Align::Left;
+        }
+        let centers: Vec<f32> = left.iter().zip(right.iter()).map(|(a, b)| 0.5 * (a + b)).collect();
+        match (spread(&left) <= tolerance, spread(&right) <= tolerance) {
+            (true, false) => Align::Left,
+            (false, true) => Align::Right,
+            (false, false) if spread(&centers) <= tolerance => Align::Center,
+            _ if numeric => Align::Right,
+            _ => Align::Left,
+        }
+    }).collect()
+}
+
+/// Number of rows at the top of the table that are headers.
+///
+/// The first row is a header if all its cells classify as headers. A title spanning
+/// the whole width may come before the actual header row; it then counts as a header too.
+fn header_rows(cells: &[TableCell], num_cols: u32, spans: &[TextSpan], styles: &StyleClassifier) -> u32 {
+    let row_cells = |row: u32| -> Vec<&TableCell> {
+        cells.iter()
+            .filter(|c| c.row == row && c.indices.len() > 0)
+            .collect()
+    };
+    let is_header = |cells: &[&TableCell]| {
+        cells.len() > 0 && cells.iter().all(|c| classify(c.indices.iter().filter_map(|&i| spans.get(i)), styles) == Class::Header)
+    };
+
+    let first = row_cells(0);
+    let title = first.len() == 1 && first[0].colspan == num_cols;
+    if title && is_header(&row_cells(1)) {
+        2
+    } else if is_header(&first) {
+        1
+    } else {
+        0
+    }
+}
+
 #[derive(Debug)]
 pub enum Node {
     Final { indices: Vec<usize> },
     Grid { x: Vec<f32>, y: Vec<f32>, cells: Vec<Node>, tag: NodeTag },
-    Table { table: Table<Vec<usize>> },
+    /// `header_rows` is the number of rows at the top that are headers.
+    Table { table: Table<TableCell>, header_rows: u32, alignment: Vec<Align> },
+}
+
+/// Content of a table cell together with its position, so merged cells survive
+/// conversion of the table.
+#[derive(Debug, Clone)]
+pub struct TableCell {
+    pub indices: Vec<usize>,
+    pub row: u32,
+    pub col: u32,
+    pub rowspan: u32,
+    pub colspan: u32,
 }
 impl Node {
-    fn tag(&self) -> NodeTag {
+    pub fn tag(&self) -> NodeTag {
         match *self {
             Node::Grid { tag, .. } => tag,
             Node::Table { .. } => NodeTag::Complex,
             Node::Final { .. } => NodeTag::Singleton,
         }
     }
-    fn indices(&self, out: &mut Vec<usize>) {
+    /// Append the indices of all spans in this node to `out`.
+    pub fn indices(&self, out: &mut Vec<usize>) {
         match *self {
             Node::Final { ref indices } => out.extend_from_slice(&indices),
             Node::Grid { ref cells, .. } => {
@@ -361,10 +866,10 @@ impl Node {
                     n.indices(out);
                 }
             }
-            Node::Table { ref table } => {
+            Node::Table { ref table, .. } => {
                 out.extend(
                     table.values()
-                        .flat_map(|v| v.value.iter())
+                        .flat_map(|v| v.value.indices.iter())
                         .cloned()
                 );
             }
@@ -373,6 +878,39 @@ impl Node {
     fn singleton(nodes: &[(RectF, usize)]) -> Self {
         Node::Final { indices: nodes.iter().map(|t| t.1).collect() }
     }
+    /// Walk the tree depth first and call `visitor` for each node.
+    ///
+    /// The cells of a grid are visited in the order they are stored, row by row.
+    /// `spans` are the spans the indices refer to.
+    pub fn visit(&self, spans: &[TextSpan], visitor: &mut impl NodeVisitor) {
+        match *self {
+            Node::Final { ref indices } => visitor.final_node(spans, indices),
+            Node::Grid { ref x, ref y, ref cells, tag } => {
+                if visitor.enter_grid(spans, x, y, tag) {
+                    for n in cells {
+                        n.visit(spans, visitor);
+                    }
+                }
+                visitor.leave_grid(spans, tag);
+            }
+            Node::Table { ref table, header_rows, ref alignment } => visitor.table(spans, table, header_rows, alignment),
+        }
+    }
+}
+
+/// Callbacks for [`Node::visit`]. They all do nothing by default.
+#[allow(unused_variables)]
+pub trait NodeVisitor {
+    /// A leaf holding the spans at `indices`.
+    fn final_node(&mut self, spans: &[TextSpan], indices: &[usize]) {}
+    /// Called before the cells of a grid split at `x` and `y`. Return `false` to skip the cells.
+    fn enter_grid(&mut self, spans: &[TextSpan], x: &[f32], y: &[f32], tag: NodeTag) -> bool {
+        true
+    }
+    /// Called after the cells of a grid (also when they were skipped).
+    fn leave_grid(&mut self, spans: &[TextSpan], tag: NodeTag) {}
+    /// A table with `header_rows` header rows and the alignment of each column.
+    fn table(&mut self, spans: &[TextSpan], table: &Table<TableCell>, header_rows: u32, alignment: &[Align]) {}
 }
 
 #[derive(PartialOrd, Ord, Eq, PartialEq, Clone, Copy, Debug)]
@@ -381,20 +919,173 @@ pub enum NodeTag {
     Line,
     Paragraph,
     Complex,
+    /// footnotes below a rule at the bottom of the page
+    Footnote,
+}
+
+/// The spans at `indices`, together with their index.
+fn indexed<'a>(spans: &'a [TextSpan], indices: &'a [usize]) -> impl Iterator<Item=(usize, &'a TextSpan)> + Clone + 'a {
+    indices.iter().flat_map(move |&i| spans.get(i).map(|s| (i, s)))
+}
+
+/// Take the footnote number (or symbol) off the start of `words`.
+fn footnote_marker(words: &mut Vec<Word>) -> Option<String> {
+    let first = words.first_mut()?;
+    let is_symbol = |c: char| matches!(c, '*' | '†' | '‡' | '§' | '¶');
+    if is_number(&first.text) || (first.text.len() > 0 && first.text.chars().all(is_symbol)) {
+        if words.len() == 1 {
+            return None;
+        }
+        return Some(words.remove(0).text);
+    }
+    // a superscript number glued to the first word
+    let digits = first.text.find(|c: char| !c.is_ascii_digit())?;
+    if digits == 0 {
+        return None;
+    }
+    let marker = first.text[..digits].to_string();
+    first.drop_prefix(digits);
+    Some(marker)
 }
 
-pub fn items(mut flow: &mut Flow, spans: &[TextSpan], node: &Node, x_anchor: f32) {
+/// The cells of a grid with `cols` columns and `rows` rows (stored row by row)
+/// in reading order: each column from top to bottom, and the columns from left to right
+/// (or right to left with `rtl`).
+///
+/// Yields the column of each cell along with it.
+fn reading_order(cols: usize, rows: usize, cells: &[Node], rtl: bool) -> impl Iterator<Item=(usize, &Node)> {
+    debug_assert_eq!(cells.len(), cols * rows);
+    (0 .. cols)
+        .map(move |i| if rtl { cols - 1 - i } else { i })
+        .flat_map(move |col| (0 .. rows).filter_map(move |row| cells.get(row * cols + col).map(|n| (col, n))))
+}
+
+pub fn items(flow: &mut Flow, spans: &[TextSpan], node: &Node, x_anchor: f32, opts: &Options, classes: &ClassCache) {
+    let page = PageInfo {
+        avg_font_size: avg(spans.iter().map(|s| s.font_size)).unwrap_or(0.0),
+        // columns of right-to-left pages are read from right to left
+        rtl: opts.bidi && is_rtl(&spans.iter().map(|s| s.text.as_str()).collect::<String>()),
+    };
+    _items(flow, spans, node, x_anchor, opts, classes, &page)
+}
+
+/// Emit the spans in the order they were drawn, as one paragraph per line.
+///
+/// A new line starts whenever a span does not share the vertical extent of the previous one
+/// or jumps back to the left of it.
+pub fn content_order(flow: &mut Flow, spans: &[TextSpan], opts: &Options) {
+    let mut order: Vec<usize> = (0 .. spans.len()).collect();
+    order.sort_by_key(|&i| spans[i].op_nr);
+
+    for line in stream_lines(spans, &order) {
+        let mut text = String::new();
+        flow.add_line(concat_text(&mut text, indexed(spans, &line), opts), RunType::Paragraph);
+    }
+}
+
+/// Break the spans at `order` into lines, keeping the order.
+///
+/// A new line starts whenever a span does not share the vertical extent of the previous one
+/// or jumps back to the left of it.
+fn stream_lines(spans: &[TextSpan], order: &[usize]) -> Vec<Vec<usize>> {
+    let mut lines = vec![];
+    let mut line: Vec<usize> = vec![];
+    let mut last: Option<RectF> = None;
+    for &i in order {
+        let r = spans[i].rect;
+        let same_line = match last {
+            Some(l) => r.center().y() > l.min_y() && r.center().y() < l.max_y() && r.min_x() > l.max_x() - 0.5 * r.height(),
+            None => true
+        };
+        if !same_line {
+            lines.push(take(&mut line));
+        }
+        line.push(i);
+        last = Some(r);
+    }
+    if line.len() > 0 {
+        lines.push(line);
+    }
+    lines
+}
+
+/// The marked-content sequence a text operator is in, see [`Options::use_struct_tree`](crate::Options::use_struct_tree).
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarkedContent {
+    /// not part of the content, like running headers and page numbers
+    Artifact,
+    /// `tag` is the structure type of the sequence with the id `mcid`, like "P" or "H1"
+    Tagged { tag: String, mcid: i64 },
+}
+
+/// Emit one run per marked-content sequence, in the order of their ids.
+///
+/// `marks` maps the `op_nr` of the spans to the sequence they are in. The structure type decides
+/// the kind of the run: headings become headers, notes footnotes and code code. Artifacts are dropped,
+/// and spans outside of any sequence come last, in the order they were drawn.
+pub fn struct_order(flow: &mut Flow, spans: &[TextSpan], marks: &HashMap<usize, MarkedContent>, opts: &Options) {
+    // (mcid, structure type, span indices) in drawing order
+    let mut groups: Vec<(Option<i64>, &str, Vec<usize>)> = vec![];
+    let mut order: Vec<usize> = (0 .. spans.len()).collect();
+    order.sort_by_key(|&i| spans[i].op_nr);
+    for i in order {
+        let (mcid, tag) = match marks.get(&spans[i].op_nr) {
+            Some(MarkedContent::Artifact) => continue,
+            Some(MarkedContent::Tagged { tag, mcid }) => (Some(*mcid), tag.as_str()),
+            None => (None, ""),
+        };
+        match groups.iter_mut().find(|g| g.0 == mcid && mcid.is_some()) {
+            Some(group) => group.2.push(i),
+            None => groups.push((mcid, tag, vec![i])),
+        }
+    }
+    // untagged text last
+    groups.sort_by_key(|g| g.0.unwrap_or(i64::MAX));
+
+    for (_, tag, indices) in groups {
+        // heading levels are assigned from the text height later, like for untagged pages
+        let kind = match tag {
+            "H" | "H1" | "H2" | "H3" | "H4" | "H5" | "H6" => RunType::Header,
+            "Note" => RunType::Footnote { marker: None },
+            "Code" => RunType::Code,
+            "TD" | "TH" => RunType::Cell,
+            _ => RunType::Paragraph,
+        };
+        let lines: Vec<Line> = stream_lines(spans, &indices).iter()
+            .map(|line| {
+                let mut text = String::new();
+                Line { words: concat_text(&mut text, indexed(spans, line), opts) }
+            })
+            .filter(|line| line.words.len() > 0)
+            .collect();
+        if lines.len() > 0 {
+            flow.runs.push(Run { lines, kind, level: 0, style: RunStyle::default(), line_breaks: opts.preserve_line_breaks });
+        }
+    }
+}
+
+/// What [`items`] needs to know about the whole page.
+struct PageInfo {
+    avg_font_size: f32,
+    rtl: bool,
+}
+
+fn _items(mut flow: &mut Flow, spans: &[TextSpan], node: &Node, x_anchor: f32, opts: &Options, classes: &ClassCache, page: &PageInfo) {
     match *node {
         Node::Final { ref indices } => {
             if indices.len() > 0 {
                 let node_spans = indices.iter().flat_map(|&i| spans.get(i));
                 let bbox = node_spans.clone().map(|s| s.rect).reduce(|a, b| a.union_rect(b)).unwrap();
-                let class = classify(node_spans.clone());
+                let class = classify_with_context(indices, spans, classes, &opts.styles, page.avg_font_size);
                 let mut text = String::new();
-                let words = concat_text(&mut text, node_spans);
+                let words = match class {
+                    Class::Code => concat_code(indexed(spans, indices), bbox.min_x(), opts),
+                    _ => concat_text(&mut text, indexed(spans, indices), opts),
+                };
                 
                 let t = match class {
-                    Class::Header => RunType::Header,
+                    Class::Header | Class::Title => RunType::Header,
+                    Class::Code => RunType::Code,
                     _ => RunType::Paragraph,
                 };
                 flow.add_line(words, t);
@@ -407,14 +1098,21 @@ pub fn items(mut flow: &mut Flow, spans: &[TextSpan], node: &Node, x_anchor: f32
                     let mut indices = vec![];
                     node.indices(&mut indices);
                     let line_spans = indices.iter().flat_map(|&i| spans.get(i));
-                    let bbox: RectF = line_spans.clone().map(|s| s.rect).reduce(|a, b| a.union_rect(b)).unwrap().into();
+                    let bbox: RectF = match line_spans.clone().map(|s| s.rect).reduce(|a, b| a.union_rect(b)) {
+                        Some(bbox) => bbox,
+                        None => return
+                    };
 
                     let mut text = String::new();
-                    let words = concat_text(&mut text, line_spans.clone());
-                    let class = classify(line_spans.clone());
+                    let class = classify_with_context(&indices, spans, classes, &opts.styles, page.avg_font_size);
+                    let words = match class {
+                        Class::Code => concat_code(indexed(spans, &indices), bbox.min_x(), opts),
+                        _ => concat_text(&mut text, indexed(spans, &indices), opts),
+                    };
 
                     let t = match class {
-                        Class::Header => RunType::Header,
+                        Class::Header | Class::Title => RunType::Header,
+                        Class::Code => RunType::Code,
                         _ => RunType::Paragraph,
                     };
                     flow.add_line(words, t);
@@ -433,12 +1131,28 @@ pub fn items(mut flow: &mut Flow, spans: &[TextSpan], node: &Node, x_anchor: f32
                         }
                     }
 
+                    if lines.len() == 0 {
+                        return;
+                    }
                     let para_spans = indices.iter().flat_map(|&i| spans.get(i));
-                    let class = classify(para_spans.clone());
+                    let class = classify_with_context(&indices, spans, classes, &opts.styles, page.avg_font_size);
                     let bbox = lines.iter().map(|t| t.0).reduce(|a, b| a.union_rect(b)).unwrap();
-                    let line_height = avg(para_spans.map(|s| s.rect.height())).unwrap();
+
+                    if class == Class::Code {
+                        // keep every line and its indentation as it is
+                        let mut start = 0;
+                        let mut flow_lines = vec![];
+                        for &(_, end) in lines.iter() {
+                            flow_lines.push(Line { words: concat_code(indexed(spans, &indices[start..end]), bbox.min_x(), opts) });
+                            start = end;
+                        }
+                        flow.runs.push(Run { lines: flow_lines, kind: RunType::Code, level: 0, style: RunStyle::default(), line_breaks: true });
+                        return;
+                    }
+
+                    let line_height = avg(para_spans.map(|s| s.rect.height())).unwrap_or(0.0);
                     // classify the lines by this vertical line
-                    let left_margin = bbox.min_x() + 0.5 * line_height;
+                    let left_margin = bbox.min_x() + opts.paragraph_indent * line_height;
 
                     // count how many are right and left of the split.
                     let mut left = 0;
@@ -454,9 +1168,23 @@ pub fn items(mut flow: &mut Flow, spans: &[TextSpan], node: &Node, x_anchor: f32
 
                     // typically paragraphs are indented to the right and longer than 2 lines.
                     // then there will be a higher left count than right count.
-                    let indent = left > right;
+                    let style = opts.paragraph_style.unwrap_or(if left > right {
+                        ParagraphStyle::FirstLineIndent
+                    } else {
+                        ParagraphStyle::Hanging
+                    });
+
+                    // a gap this much wider than the usual line spacing separates paragraphs
+                    let block_gap = if style == ParagraphStyle::BlockSpacing {
+                        let mut gaps: Vec<f32> = lines.windows(2).map(|w| w[1].0.min_y() - w[0].0.max_y()).collect();
+                        gaps.sort_unstable_by(|a, b| a.total_cmp(b));
+                        gaps.get(gaps.len() / 2).cloned().unwrap_or(0.0) + 0.5 * line_height
+                    } else {
+                        0.0
+                    };
 
                     let mut para_start = 0;
+                    let mut prev_bottom = bbox.min_y();
                     let mut line_start = 0;
                     let mut text = String::new();
                     let mut para_bbox = RectF::default();
@@ -464,13 +1192,24 @@ pub fn items(mut flow: &mut Flow, spans: &[TextSpan], node: &Node, x_anchor: f32
                     for &(line_bbox, end) in lines.iter() {
                         if line_start != 0 {
                             // if a line is indented (or outdented), it marks a new paragraph
-                            if (line_bbox.min_x() >= left_margin) == indent {
+                            let new_para = match style {
+                                ParagraphStyle::FirstLineIndent => line_bbox.min_x() >= left_margin,
+                                ParagraphStyle::Hanging => line_bbox.min_x() < left_margin,
+                                ParagraphStyle::BlockSpacing => line_bbox.min_y() - prev_bottom > block_gap,
+                            };
+                            if new_para {
+                                if opts.dehyphenate {
+                                    dehyphenate(&mut flow_lines);
+                                }
                                 flow.runs.push(Run {
                                     lines: take(&mut flow_lines),
                                     kind: match class {
-                                        Class::Header => RunType::Header,
+                                        Class::Header | Class::Title => RunType::Header,
                                         _ => RunType::Paragraph
-                                    }
+                                    },
+                                    level: 0,
+                                    style: RunStyle::default(),
+                                    line_breaks: opts.preserve_line_breaks,
                                 });
                                 para_start = line_start;
                             } else {
@@ -478,7 +1217,7 @@ pub fn items(mut flow: &mut Flow, spans: &[TextSpan], node: &Node, x_anchor: f32
                             }
                         }
                         if end > line_start {
-                            let words = concat_text(&mut text, indices[line_start..end].iter().flat_map(|&i| spans.get(i)));
+                            let words = concat_text(&mut text, indexed(spans, &indices[line_start..end]), opts);
 
                             if words.len() > 0 {
                                 flow_lines.push(Line { words });
@@ -489,55 +1228,77 @@ pub fn items(mut flow: &mut Flow, spans: &[TextSpan], node: &Node, x_anchor: f32
                         } else {
                             para_bbox = para_bbox.union_rect(line_bbox);
                         }
+                        prev_bottom = line_bbox.max_y();
                         line_start = end;
                     }
 
+                    if opts.dehyphenate {
+                        dehyphenate(&mut flow_lines);
+                    }
                     flow.runs.push(Run {
                         lines: flow_lines,
                         kind: match class {
-                            Class::Header => RunType::Header,
+                            Class::Header | Class::Title => RunType::Header,
                             _ => RunType::Paragraph
-                        }
+                        },
+                        level: 0,
+                        style: RunStyle::default(),
+                        line_breaks: opts.preserve_line_breaks,
                     });
                 }
+                NodeTag::Footnote => {
+                    let mut notes = Flow::new();
+                    for node in cells {
+                        _items(&mut notes, spans, node, x_anchor, opts, classes, page);
+                    }
+                    for mut run in notes.runs {
+                        let marker = run.lines.first_mut().and_then(|l| footnote_marker(&mut l.words));
+                        run.kind = RunType::Footnote { marker };
+                        flow.runs.push(run);
+                    }
+                }
                 NodeTag::Complex => {
-                    let x_anchors = once(x_anchor).chain(x.iter().cloned()).cycle();
-                    for (node, x) in cells.iter().zip(x_anchors) {
-                        items(flow, spans, node, x);
+                    let x_anchors: Vec<f32> = once(x_anchor).chain(x.iter().cloned()).collect();
+                    for (col, node) in reading_order(x.len() + 1, y.len() + 1, cells, page.rtl) {
+                        _items(flow, spans, node, x_anchors[col], opts, classes, page);
                     }
                 }
             }
         }
-        Node::Table { ref table } => {
+        Node::Table { ref table, header_rows, ref alignment } => {
             if let Some(bbox) = table.values()
-                .flat_map(|v| v.value.iter().flat_map(|&i| spans.get(i).map(|s| s.rect)))
+                .flat_map(|v| v.value.indices.iter().flat_map(|&i| spans.get(i).map(|s| s.rect)))
                 .reduce(|a, b| a.union_rect(b)) {
-                let table = table.flat_map(|indices| {
-                    if indices.len() == 0 {
+                let table = table.flat_map(|cell| {
+                    if cell.indices.len() == 0 {
                         None
                     } else {
-                        let line_spans = indices.iter().flat_map(|&i| spans.get(i));
+                        let line_spans = cell.indices.iter().flat_map(|&i| spans.get(i));
                         let bbox: RectF = line_spans.clone().map(|s| s.rect).reduce(|a, b| a.union_rect(b)).unwrap().into();
 
                         let mut text = String::new();
-                        concat_text(&mut text, line_spans.clone());
+                        concat_text(&mut text, indexed(spans, &cell.indices), opts);
                         Some(CellContent {
                             text,
                             rect: bbox.into(),
+                            row: cell.row,
+                            col: cell.col,
+                            rowspan: cell.rowspan,
+                            colspan: cell.colspan,
                         })
                     }
                 });
-                flow.add_table(table);
+                flow.add_table(table, header_rows, alignment.clone());
             }
         }
     }
 }
 
 
-pub fn render(w: &mut String, spans: &[TextSpan], node: &Node, bbox: RectF) {
-    _render(w, spans, node, bbox, 0)
+pub fn render(w: &mut String, spans: &[TextSpan], node: &Node, bbox: RectF, styles: &StyleClassifier) {
+    _render(w, spans, node, bbox, styles, 0)
 }
-fn _render(w: &mut String, spans: &[TextSpan], node: &Node, bbox: RectF, level: usize) {
+fn _render(w: &mut String, spans: &[TextSpan], node: &Node, bbox: RectF, styles: &StyleClassifier, level: usize) {
     use std::fmt::Write;
 
     match *node {
@@ -555,7 +1316,7 @@ fn _render(w: &mut String, spans: &[TextSpan], node: &Node, bbox: RectF, level:
             */
             
             if indices.len() > 0 {
-                let class = classify(indices.iter().cloned().filter_map(|i| spans.get(i)));
+                let class = classify(indices.iter().cloned().filter_map(|i| spans.get(i)), styles);
 
                 for &i in indices.iter() {
                     let r = spans[i].rect;
@@ -586,7 +1347,7 @@ fn _render(w: &mut String, spans: &[TextSpan], node: &Node, bbox: RectF, level:
                     }
 
                     let bbox = RectF::from_points(Vector2F::new(min_x, min_y), Vector2F::new(max_x, max_y));
-                    _render(w, spans, cell, bbox, level+1);
+                    _render(w, spans, cell, bbox, styles, level+1);
                 }
             }
         }
@@ -596,23 +1357,36 @@ fn _render(w: &mut String, spans: &[TextSpan], node: &Node, bbox: RectF, level:
     }
 }
 
-fn split(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines: &Lines) -> Node {
+fn split(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines: &Lines, opts: &Options, deadline: &Deadline) -> Node {
+    _split(boxes, spans, lines, opts, deadline, 0)
+}
+
+/// Nesting depth after which [`split`] stops recursing, so pathological pages
+/// cannot overflow the stack.
+const MAX_SPLIT_DEPTH: usize = 64;
+
+fn _split(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines: &Lines, opts: &Options, deadline: &Deadline, depth: usize) -> Node {
     let num_boxes = boxes.len();
     if num_boxes < 2 {
         return Node::singleton(boxes);
     }
+    if deadline.check() || depth >= MAX_SPLIT_DEPTH {
+        // out of time or too deep: just group the remaining boxes into lines
+        return overlapping_lines(boxes);
+    }
 
+    // collect the gaps along x and along y
     sort_x(boxes);
-    let max_x_gap = dist_x(boxes);
+    let x_list: Vec<(f32, f32)> = gap_list(boxes, |r| (r.min_x(), r.max_x())).map(|(a, b, _)| (a, b)).collect();
     sort_y(boxes);
-    let max_y_gap = dist_y(boxes);
+    let y_list: Vec<(f32, f32)> = gap_list(boxes, |r| (r.min_y(), r.max_y())).map(|(a, b, _)| (a, b)).collect();
 
-    let x_y_ratio = 1.0;
+    let x_y_ratio = opts.x_y_ratio;
 
-    let max_gap = match (max_x_gap, max_y_gap) {
-        (Some((x, _)), Some((y, _))) => x.max(y * x_y_ratio),
-        (Some((x, _)), None) => x,
-        (None, Some((y, _))) => y * x_y_ratio,
+    let max_gap = match (widest(&x_list), widest(&y_list)) {
+        (Some(x), Some(y)) => x.max(y * x_y_ratio),
+        (Some(x), None) => x,
+        (None, Some(y)) => y * x_y_ratio,
         (None, None) => {
             sort_x(boxes);
             return Node::singleton(boxes);
@@ -622,22 +1396,33 @@ fn split(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines: &Lines) -> Nod
     let y_threshold = (max_gap * 0.5 / x_y_ratio).max(0.1);
     let mut cells = vec![];
 
-    let y_gaps: Vec<f32> = gaps(y_threshold, boxes, |r| (r.min_y(), r.max_y()))
-        .collect();
-    
     sort_x(boxes);
-    let x_gaps: Vec<f32> = gaps(x_threshold, boxes, |r| (r.min_x(), r.max_x()))
-        .collect();
+    let y_gaps = wide_gaps(&y_list, y_threshold);
+    let column_hint = opts.expected_columns.filter(|_| depth == 0);
+    let mut x_gaps = match column_hint {
+        Some(n) => widest_gaps(&x_list, n.saturating_sub(1)),
+        None => wide_gaps(&x_list, x_threshold),
+    };
+
+    let mut sorted_y = false;
+    if column_hint.is_none() && y_gaps.len() == 0 && x_gaps.len() > 0 {
+        // text flowing around a figure leaves gaps that look like columns
+        sort_y(boxes);
+        sorted_y = true;
+        x_gaps.retain(|&x| !is_wrap_gap(boxes, x));
+    }
 
     if x_gaps.len() == 0 && y_gaps.len() == 0 {
         return overlapping_lines(boxes);
     }
 
-    if x_gaps.len() > 1 && y_gaps.len() > 1 {
-        return split2(boxes, spans, lines);
+    if opts.detect_tables && x_gaps.len() > 1 && y_gaps.len() > 1 {
+        return split2(boxes, spans, lines, opts);
     }
 
-    sort_y(boxes);
+    if !sorted_y {
+        sort_y(boxes);
+    }
     for row in split_by(boxes, &y_gaps, |r| r.min_y()) {
 
         if x_gaps.len() > 0 {
@@ -645,10 +1430,10 @@ fn split(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines: &Lines) -> Nod
             for cell in split_by(row, &x_gaps, |r| r.min_x()) {
                 sort_y(cell);
                 assert!(cell.len() < num_boxes);
-                cells.push(split(cell, spans, lines));
+                cells.push(_split(cell, spans, lines, opts, deadline, depth + 1));
             }
         } else {
-            cells.push(split(row, spans, lines));
+            cells.push(_split(row, spans, lines, opts, deadline, depth + 1));
         }
     }
 
@@ -676,6 +1461,63 @@ fn split(boxes: &mut [(RectF, usize)], spans: &[TextSpan], lines: &Lines) -> Nod
         tag,
     }
 }
+/// Decide whether the vertical gap at `x` separates real columns or is just
+/// whitespace left by text wrapping around an irregular shape.
+///
+/// Real columns have at least one aligned edge next to the gap (the end of the
+/// left column or the start of the right one). If both edges vary from line to line,
+/// the gap follows the outline of something else and the text is a single column.
+///
+/// `boxes` has to be sorted by y.
+fn is_wrap_gap(boxes: &[(RectF, usize)], x: f32) -> bool {
+    let avg_height = match avg(boxes.iter().map(|(r, _)| r.height())) {
+        Some(h) => h,
+        None => return false
+    };
+
+    let mut ends = vec![];
+    let mut starts = vec![];
+    let mut line_bottom: Option<f32> = None;
+    let mut end = None;
+    let mut start = None;
+    for &(r, _) in boxes.iter() {
+        match line_bottom {
+            Some(max_y) if r.min_y() < max_y => {}
+            _ => {
+                if let (Some(e), Some(s)) = (end.take(), start.take()) {
+                    ends.push(e);
+                    starts.push(s);
+                }
+                line_bottom = Some(r.max_y());
+            }
+        }
+        if r.max_x() < x {
+            end = Some(end.map_or(r.max_x(), |e: f32| e.max(r.max_x())));
+        } else if r.min_x() > x {
+            start = Some(start.map_or(r.min_x(), |s: f32| s.min(r.min_x())));
+        }
+    }
+    if let (Some(e), Some(s)) = (end, start) {
+        ends.push(e);
+        starts.push(s);
+    }
+
+    // not enough evidence
+    if starts.len() < 3 {
+        return false;
+    }
+
+    let tolerance = 0.5 * avg_height;
+    let aligned = |values: &mut Vec<f32>| {
+        values.sort_unstable_by(|a, b| a.total_cmp(b));
+        let median = values[values.len() / 2];
+        let count = values.iter().filter(|&&v| (v - median).abs() < tolerance).count();
+        count * 4 >= values.len() * 3
+    };
+
+    !aligned(&mut ends) && !aligned(&mut starts)
+}
+
 #[allow(dead_code)]
 fn split_v(boxes: &mut [(RectF, usize)]) -> Node {
     let num_boxes = boxes.len();
@@ -717,15 +1559,17 @@ fn split_v(boxes: &mut [(RectF, usize)]) -> Node {
     }
 }
 
-fn top_bottom_gap(boxes: &mut [(RectF, usize)], bbox: RectF) -> (Option<usize>, Option<usize>) {
+/// Find the gaps that separate a header in the top `header_band` and a footer in the
+/// bottom `footer_band` (fractions of the page height) from the rest.
+fn top_bottom_gap(boxes: &mut [(RectF, usize)], bbox: RectF, header_band: f32, footer_band: f32) -> (Option<usize>, Option<usize>) {
     let num_boxes = boxes.len();
     if num_boxes < 2 {
         return (None, None);
     }
 
     let mut gaps = gap_list(boxes, |r| (r.min_y(), r.max_y()));
-    let top_limit = bbox.min_y() + bbox.height() * 0.2;
-    let bottom_limit = bbox.min_y() + bbox.height() * 0.8;
+    let top_limit = bbox.min_y() + bbox.height() * header_band;
+    let bottom_limit = bbox.max_y() - bbox.height() * footer_band;
     match gaps.next() {
         Some((y, _, top)) if y < top_limit => {
             match gaps.last() {
@@ -737,15 +1581,16 @@ fn top_bottom_gap(boxes: &mut [(RectF, usize)], bbox: RectF) -> (Option<usize>,
         _ => (None, None)
     }
 }
-fn left_right_gap(boxes: &mut [(RectF, usize)], bbox: RectF) -> (Option<usize>, Option<usize>) {
+/// Like [`top_bottom_gap`], for margins in the left and right bands.
+fn left_right_gap(boxes: &mut [(RectF, usize)], bbox: RectF, left_band: f32, right_band: f32) -> (Option<usize>, Option<usize>) {
     let num_boxes = boxes.len();
     if num_boxes < 2 {
         return (None, None);
     }
 
     let mut gaps = gap_list(boxes, |r| (r.min_x(), r.max_x()));
-    let left_limit = bbox.min_x() + bbox.width() * 0.2;
-    let right_limit = bbox.min_x() + bbox.width() * 0.8;
+    let left_limit = bbox.min_x() + bbox.width() * left_band;
+    let right_limit = bbox.max_x() - bbox.width() * right_band;
     match gaps.next() {
         Some((x, _, left)) if x < left_limit  => {
             match gaps.last() {
@@ -758,15 +1603,23 @@ fn left_right_gap(boxes: &mut [(RectF, usize)], bbox: RectF) -> (Option<usize>,
     }
 }
 
+/// sort `boxes` by their left edge
 fn sort_x(boxes: &mut [(RectF, usize)]) {
-    boxes.sort_unstable_by(|a, b| a.0.min_x().partial_cmp(&b.0.min_x()).unwrap());
+    boxes.sort_unstable_by(|a, b| a.0.min_x().total_cmp(&b.0.min_x()));
 }
+/// sort `boxes` by their top edge
 fn sort_y(boxes: &mut [(RectF, usize)]) {
-    boxes.sort_unstable_by(|a, b| a.0.min_y().partial_cmp(&b.0.min_y()).unwrap());
+    boxes.sort_unstable_by(|a, b| a.0.min_y().total_cmp(&b.0.min_y()));
 }
 fn overlapping_lines(boxes: &mut [(RectF, usize)]) -> Node {
     sort_y(boxes);
-    let avg_height = avg(boxes.iter().map(|(r, _)| r.height())).unwrap();
+    // some fonts produce rects without height. keep a minimum, so chars on the
+    // same line still end up together and distinct lines stay apart.
+    const MIN_LINE_HEIGHT: f32 = 1.0;
+    let avg_height = match avg(boxes.iter().map(|(r, _)| r.height().max(0.0))) {
+        Some(h) => h.max(MIN_LINE_HEIGHT),
+        None => return Node::singleton(boxes)
+    };
     
     let mut y_center = boxes[0].0.center().y();
     let mut lines = vec![];
@@ -844,9 +1697,23 @@ fn max_gap(boxes: &[(RectF, usize)], span: impl Fn(&RectF) -> (f32, f32)) -> Opt
     .map(|(a, b, _)| (b - a, 0.5 * (a + b)))
 }
 
-fn dist_x(boxes: &[(RectF, usize)]) -> Option<(f32, f32)> {
-    max_gap(boxes, |r| (r.min_x(), r.max_x()))
+/// size of the widest gap in a list from [`gap_list`]
+fn widest(list: &[(f32, f32)]) -> Option<f32> {
+    list.iter().map(|&(a, b)| b - a).max_by_key(|&d| NotNan::new(d).unwrap())
+}
+/// centers of the gaps in a list from [`gap_list`] that are at least `threshold` wide
+fn wide_gaps(list: &[(f32, f32)], threshold: f32) -> Vec<f32> {
+    list.iter().filter(|&&(a, b)| b - a >= threshold).map(|&(a, b)| 0.5 * (a + b)).collect()
+}
+/// centers of the `n` widest gaps in a list from [`gap_list`], from left to right
+fn widest_gaps(list: &[(f32, f32)], n: usize) -> Vec<f32> {
+    let mut list = list.to_vec();
+    list.sort_unstable_by(|a, b| (b.1 - b.0).total_cmp(&(a.1 - a.0)));
+    list.truncate(n);
+    list.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+    list.iter().map(|&(a, b)| 0.5 * (a + b)).collect()
 }
+
 fn dist_y(boxes: &[(RectF, usize)]) -> Option<(f32, f32)> {
     max_gap(boxes, |r| (r.min_y(), r.max_y()))
 }
@@ -893,6 +1760,10 @@ use super::util::Tri;
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum Class {
     Number,
+    /// much larger than the rest of the page
+    Title,
+    /// in a monospace font
+    Code,
     Header,
     Paragraph,
     Mixed,
@@ -925,18 +1796,82 @@ impl TriCount {
         }
     }
 }
-fn classify<'a>(spans: impl Iterator<Item=&'a TextSpan>) -> Class {
+
+/// Whether the span is set in a monospace font: known by name, or all glyphs are equally wide.
+fn is_monospace(span: &TextSpan) -> bool {
+    if let Some(ref font) = span.font {
+        let name = font.name.to_lowercase();
+        if ["mono", "courier", "consolas", "menlo", "inconsolata"].iter().any(|m| name.contains(m)) {
+            return true;
+        }
+    }
+    let mut widths = span.chars.iter()
+        .filter(|c| !span.text[c.offset..].starts_with(char::is_whitespace))
+        .map(|c| c.width);
+    let first = match widths.next() {
+        Some(w) if w > 0.0 => w,
+        _ => return false
+    };
+    let mut count = 1;
+    for w in widths {
+        if (w - first).abs() > 0.02 * first {
+            return false;
+        }
+        count += 1;
+    }
+    // too few glyphs to tell
+    count >= 8
+}
+
+/// Like [`classify`], but spans more than twice the average font size of the page
+/// (`avg_font_size`) are a [`Class::Title`], whether bold or not.
+fn classify_with_context(indices: &[usize], spans: &[TextSpan], classes: &ClassCache, styles: &StyleClassifier, avg_font_size: f32) -> Class {
+    match avg(indices.iter().filter_map(|&i| spans.get(i)).map(|s| s.font_size)) {
+        Some(size) if avg_font_size > 0.0 && size > 2.0 * avg_font_size => Class::Title,
+        _ => classes.classify(indices.iter().cloned(), spans, styles)
+    }
+}
+
+/// Remembers the [`Class`] of each set of spans, since the same regions are classified
+/// while building the tree and again when turning it into a flow.
+#[derive(Default)]
+pub struct ClassCache {
+    classes: RefCell<HashMap<Vec<usize>, Class>>,
+}
+impl ClassCache {
+    fn classify(&self, indices: impl Iterator<Item=usize>, spans: &[TextSpan], styles: &StyleClassifier) -> Class {
+        let mut key: Vec<usize> = indices.collect();
+        key.sort_unstable();
+        if let Some(&class) = self.classes.borrow().get(&key) {
+            return class;
+        }
+        let class = classify(key.iter().filter_map(|&i| spans.get(i)), styles);
+        self.classes.borrow_mut().insert(key, class);
+        class
+    }
+}
+fn classify<'a>(spans: impl Iterator<Item=&'a TextSpan>, styles: &StyleClassifier) -> Class {
     use pdf_render::FontEntry;
 
     let mut bold = TriCount::new();
     let mut numeric = TriCount::new();
     let mut uniform = TriCount::new();
     let mut first_font: *const FontEntry = std::ptr::null();
+    let mut letters = 0;
+    let mut upper = 0;
+    let mut monospace = TriCount::new();
 
     for s in spans {
         numeric.add(is_number(&s.text));
+        monospace.add(is_monospace(s));
+        for c in s.text.chars().filter(|c| c.is_alphabetic()) {
+            letters += 1;
+            if c.is_uppercase() {
+                upper += 1;
+            }
+        }
         if let Some(ref font) = s.font {
-            bold.add(font.name.contains("Bold"));
+            bold.add(styles.is_bold_font(font));
             let font_ptr = Arc::as_ptr(font);
             if first_font.is_null() {
                 first_font = font_ptr;
@@ -947,12 +1882,424 @@ fn classify<'a>(spans: impl Iterator<Item=&'a TextSpan>) -> Class {
     }
     uniform.add(true);
 
+    // a short text in (almost) all caps is a header even in the body font.
+    // the whole text has to qualify, so acronyms in a sentence don't count.
+    let all_caps = letters >= 3 && letters <= 60 && upper * 10 >= letters * 9;
+
     match (numeric.count(), bold.count(), uniform.count()) {
         (Tri::True, _, Tri::True) => Class::Number,
+        (_, _, Tri::True) if matches!(monospace.count(), Tri::True) => Class::Code,
         (_, Tri::True, Tri::True) => Class::Header,
+        (_, _, Tri::True) if all_caps => Class::Header,
         (_, Tri::False, Tri::True) => Class::Paragraph,
         (_, Tri::False, _) => Class::Paragraph,
         (_, Tri::Maybe(_), _) => Class::Paragraph,
         _ => Class::Mixed
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::span;
+
+    fn boxes(edges: &[(f32, f32)]) -> Vec<(RectF, usize)> {
+        edges.iter().enumerate().map(|(i, &(x, y))| {
+            (RectF::new(Vector2F::new(x, y), Vector2F::new(10.0, 10.0)), i)
+        }).collect()
+    }
+    fn order(boxes: &[(RectF, usize)]) -> Vec<usize> {
+        boxes.iter().map(|&(_, i)| i).collect()
+    }
+
+    #[test]
+    fn sort_by_edge() {
+        let mut boxes = boxes(&[(0.0, 0.0), (0.0, 20.0), (0.0, 40.0), (-5.0, 20.0)]);
+        sort_x(&mut boxes);
+        assert_eq!(order(&boxes)[0], 3);
+
+        // sorting boxes that are in order again leaves them as they are, ties included
+        sort_y(&mut boxes);
+        let sorted = order(&boxes);
+        assert_eq!(sorted[0], 0);
+        assert_eq!(sorted[3], 2);
+        sort_y(&mut boxes);
+        assert_eq!(order(&boxes), sorted);
+    }
+
+    /// boxes of `height` at `(min_x, max_x)` on lines 12 units apart
+    fn line_boxes(lines: &[&[(f32, f32)]], height: f32) -> Vec<(RectF, usize)> {
+        let mut boxes = vec![];
+        for (n, line) in lines.iter().enumerate() {
+            let y = 12.0 * n as f32;
+            for &(x0, x1) in line.iter() {
+                let i = boxes.len();
+                boxes.push((RectF::from_points(Vector2F::new(x0, y), Vector2F::new(x1, y + height)), i));
+            }
+        }
+        boxes
+    }
+
+    #[test]
+    fn columns_are_not_wrap_gaps() {
+        let line: &[(f32, f32)] = &[(0.0, 100.0), (120.0, 220.0)];
+        let boxes = line_boxes(&[line, line, line, line], 10.0);
+        assert!(!is_wrap_gap(&boxes, 110.0));
+    }
+
+    #[test]
+    fn text_around_a_shape_leaves_a_wrap_gap() {
+        let boxes = line_boxes(&[
+            &[(0.0, 100.0), (130.0, 220.0)],
+            &[(0.0, 70.0), (160.0, 220.0)],
+            &[(0.0, 40.0), (110.0, 220.0)],
+            &[(0.0, 90.0), (150.0, 220.0)],
+        ], 10.0);
+        assert!(is_wrap_gap(&boxes, 105.0));
+        // two lines are not enough to tell
+        assert!(!is_wrap_gap(&boxes[..4], 105.0));
+    }
+
+    fn table_cell(indices: &[usize], row: u32, col: u32, rowspan: u32, colspan: u32) -> TableCell {
+        TableCell { indices: indices.to_vec(), row, col, rowspan, colspan }
+    }
+
+    #[test]
+    fn table_cells_keep_their_spans() {
+        let spans = [
+            span("Name", 0.0, 0.0, 10.0), span("Total", 100.0, 0.0, 10.0),
+            span("Ann", 0.0, 20.0, 10.0), span("3", 100.0, 20.0, 10.0), span("4", 150.0, 20.0, 10.0),
+        ];
+        let mut table = Table::empty(2, 3);
+        table.set_cell(table_cell(&[0], 0, 0, 1, 1), 0, 0, 1, 1);
+        table.set_cell(table_cell(&[1], 0, 1, 1, 2), 0, 1, 1, 2);
+        table.set_cell(table_cell(&[2], 1, 0, 1, 1), 1, 0, 1, 1);
+        table.set_cell(table_cell(&[3], 1, 1, 1, 1), 1, 1, 1, 1);
+        table.set_cell(table_cell(&[4], 1, 2, 1, 1), 1, 2, 1, 1);
+        let node = Node::Table { table, header_rows: 1, alignment: vec![] };
+
+        let mut flow = Flow::new();
+        items(&mut flow, &spans, &node, 0.0, &Options::default(), &ClassCache::default());
+        let cells = &flow.tables[0].cells;
+        assert_eq!(cells.len(), 5);
+        assert_eq!((cells[1].text.as_str(), cells[1].row, cells[1].col, cells[1].colspan), ("Total", 0, 1, 2));
+        assert_eq!((cells[4].text.as_str(), cells[4].row, cells[4].col, cells[4].colspan), ("4", 1, 2, 1));
+        assert_eq!(flow.tables[0].to_strings(), [vec!["Name", "Total", ""], vec!["Ann", "3", "4"]]);
+    }
+
+    /// Lay out `spans` and the ruling `lines` on a 600 by 800 page, looking for tables
+    /// and without stripping headers and footers.
+    fn layout(spans: &[TextSpan], lines: &[([f32; 4], f32)]) -> Flow {
+        let opts = Options::default().strip_header_footer(false).detect_tables(true);
+        layout_with(spans, lines, &opts)
+    }
+    fn layout_with(spans: &[TextSpan], lines: &[([f32; 4], f32)], opts: &Options) -> Flow {
+        let bbox = RectF::new(Vector2F::zero(), Vector2F::new(600.0, 800.0));
+        let classes = ClassCache::default();
+        let root = build(spans, bbox, lines, opts, &classes, &Deadline::new(None));
+        let mut flow = Flow::new();
+        items(&mut flow, spans, &root, 0.0, opts, &classes);
+        flow
+    }
+    /// a span reading "r{row}c{col}" for each of `rows` at each x of `cols`
+    fn grid_spans(rows: &[f32], cols: &[f32]) -> Vec<TextSpan> {
+        let mut spans = vec![];
+        for (r, &y) in rows.iter().enumerate() {
+            for (c, &x) in cols.iter().enumerate() {
+                spans.push(span(&format!("r{}c{}", r, c), x, y, 10.0));
+            }
+        }
+        spans
+    }
+
+    #[test]
+    fn uneven_row_gaps_keep_rows_apart() {
+        let spans = grid_spans(&[0.0, 55.0, 120.0, 175.0], &[0.0, 100.0, 200.0]);
+        let flow = layout(&spans, &[]);
+        assert_eq!(flow.tables.len(), 1);
+        let rows = flow.tables[0].to_strings();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[3], ["r3c0", "r3c1", "r3c2"]);
+    }
+
+    #[test]
+    fn tables_are_opt_in() {
+        let spans = grid_spans(&[0.0, 55.0, 120.0, 175.0], &[0.0, 100.0, 200.0]);
+        let flow = layout_with(&spans, &[], &Options::default().strip_header_footer(false));
+        assert_eq!(flow.tables.len(), 0);
+        assert_eq!(flow.lines().map(|l| l.words.len()).sum::<usize>(), 12);
+    }
+
+    #[test]
+    fn merge_value_split_by_wide_space() {
+        let spans = [
+            span("Name", 0.0, 0.0, 10.0), span("New", 100.0, 0.0, 10.0), span("York", 130.0, 0.0, 10.0),
+            span("Ann", 0.0, 20.0, 10.0), span("Paris", 100.0, 20.0, 10.0),
+        ];
+        let cells = || vec![
+            table_cell(&[0], 0, 0, 1, 1), table_cell(&[1], 0, 1, 1, 1), table_cell(&[2], 0, 2, 1, 1),
+            table_cell(&[3], 1, 0, 1, 1), table_cell(&[4], 1, 1, 1, 1),
+        ];
+
+        let mut merged = cells();
+        merge_split_cells(&mut merged, &spans, &analyze_lines(&[], 10.0));
+        assert_eq!(merged.len(), 4);
+        assert_eq!((merged[1].col, merged[1].colspan), (1, 2));
+        assert_eq!(merged[1].indices, [1, 2]);
+
+        // a ruling line between them keeps them apart
+        let mut ruled = cells();
+        merge_split_cells(&mut ruled, &spans, &analyze_lines(&[[122.0, 0.0, 122.0, 40.0]], 10.0));
+        assert_eq!(ruled.len(), 5);
+    }
+
+    #[test]
+    fn merge_threshold_joins_close_lines() {
+        let rules = [[0.0, 0.0, 100.0, 0.0], [0.0, 5.0, 100.0, 5.0], [0.0, 8.0, 100.0, 8.0], [0.0, 30.0, 100.0, 30.0]];
+        let cases: [(f32, &[(f32, f32)]); 2] = [
+            (Options::default().line_merge_threshold, &[(0.0, 8.0), (30.0, 30.0)]),
+            (2.0, &[(0.0, 0.0), (5.0, 5.0), (8.0, 8.0), (30.0, 30.0)]),
+        ];
+        for (threshold, hlines) in cases {
+            assert_eq!(analyze_lines(&rules, threshold).hlines, hlines, "threshold {}", threshold);
+        }
+    }
+
+    /// 2 by 2 grid, stored row by row
+    fn quad() -> Vec<Node> {
+        (0 .. 4).map(|i| Node::Final { indices: vec![i] }).collect()
+    }
+    fn read(cells: &[Node], rtl: bool) -> Vec<(usize, usize)> {
+        reading_order(2, 2, cells, rtl).map(|(col, node)| {
+            let mut indices = vec![];
+            node.indices(&mut indices);
+            (col, indices[0])
+        }).collect()
+    }
+
+    #[test]
+    fn grid_reads_column_by_column() {
+        assert_eq!(read(&quad(), false), [(0, 0), (0, 2), (1, 1), (1, 3)]);
+    }
+
+    #[test]
+    fn rtl_grid_reads_right_column_first() {
+        assert_eq!(read(&quad(), true), [(1, 1), (1, 3), (0, 0), (0, 2)]);
+    }
+
+    #[test]
+    fn much_larger_text_is_a_title() {
+        let spans = [
+            span("Report", 0.0, 0.0, 30.0),
+            span("some", 0.0, 50.0, 10.0), span("body", 25.0, 50.0, 10.0),
+            span("text", 0.0, 65.0, 10.0), span("here", 25.0, 65.0, 10.0),
+        ];
+        let avg_font_size = avg(spans.iter().map(|s| s.font_size)).unwrap();
+        let class = |indices: &[usize]| classify_with_context(indices, &spans, &ClassCache::default(), &StyleClassifier::default(), avg_font_size);
+        assert_eq!(class(&[0]), Class::Title);
+        assert_ne!(class(&[1, 2]), Class::Title);
+
+        let flow = layout(&spans, &[]);
+        assert_eq!(flow.runs[0].kind, RunType::Header);
+    }
+
+    #[test]
+    fn header_footer_bands() {
+        let page = RectF::new(Vector2F::zero(), Vector2F::new(600.0, 800.0));
+        // running title, two lines of body text and a page number
+        let mut page_boxes = boxes(&[(0.0, 100.0), (0.0, 200.0), (0.0, 640.0), (0.0, 700.0)]);
+        assert_eq!(top_bottom_gap(&mut page_boxes, page, 0.2, 0.2), (Some(1), Some(3)));
+        // narrower bands leave them in the body
+        assert_eq!(top_bottom_gap(&mut page_boxes, page, 0.1, 0.1), (None, None));
+        assert_eq!(top_bottom_gap(&mut page_boxes, page, 0.2, 0.1), (Some(1), None));
+    }
+
+    #[test]
+    fn footnotes_below_a_rule() {
+        let spans = [
+            span("Body", 0.0, 100.0, 10.0), span("text.", 0.0, 115.0, 10.0),
+            span("1", 0.0, 710.0, 7.0), span("See", 10.0, 710.0, 7.0),
+        ];
+        let rule = ([0.0, 705.0, 200.0, 705.0], 0.5);
+        let flow = layout(&spans, &[rule]);
+        let note = flow.runs.last().unwrap();
+        assert_eq!(note.kind, RunType::Footnote { marker: Some("1".into()) });
+        assert_eq!(note.lines[0].words[0].text, "See");
+        assert!(flow.runs[0].kind != note.kind);
+
+        // without the rule it is just small text
+        let flow = layout(&spans, &[]);
+        assert!(flow.runs.iter().all(|r| !matches!(r.kind, RunType::Footnote { .. })));
+    }
+
+    #[test]
+    fn straighten_skewed_lines() {
+        let lines = straighten(&[[0.0, 100.0, 200.0, 101.0], [50.0, 0.0, 51.0, 300.0], [0.0, 0.0, 100.0, 100.0]], 0.01);
+        assert_eq!(lines[0], [0.0, 100.5, 200.0, 100.5]);
+        assert_eq!(lines[1], [50.5, 0.0, 50.5, 300.0]);
+        // a diagonal stays as it is
+        assert_eq!(lines[2], [0.0, 0.0, 100.0, 100.0]);
+        // too steep for the tolerance
+        assert_eq!(straighten(&[[0.0, 100.0, 200.0, 110.0]], 0.01)[0], [0.0, 100.0, 200.0, 110.0]);
+    }
+
+    #[test]
+    fn thick_dividers_are_not_cell_borders() {
+        let lines = [
+            ([0.0, 0.0, 200.0, 0.0], 0.5),
+            ([0.0, 20.0, 200.0, 20.0], 0.5),
+            ([0.0, 0.0, 0.0, 20.0], 0.5),
+            // a section divider
+            ([0.0, 50.0, 200.0, 50.0], 4.0),
+        ];
+        let borders = cell_borders(&lines);
+        assert_eq!(borders.len(), 3);
+        assert!(borders.iter().all(|l| l[1] != 50.0));
+
+        // on a page with only thick lines, they are the borders
+        assert_eq!(cell_borders(&[([0.0, 0.0, 200.0, 0.0], 2.5), ([0.0, 20.0, 200.0, 20.0], 2.5)]).len(), 2);
+    }
+
+    fn span_boxes(spans: &[TextSpan]) -> Vec<(RectF, usize)> {
+        spans.iter().enumerate().map(|(i, s)| (s.rect, i)).collect()
+    }
+
+    #[test]
+    fn split_stops_at_max_depth() {
+        let spans = grid_spans(&[0.0], &[0.0, 100.0]);
+        let lines = analyze_lines(&[], 10.0);
+        let (opts, deadline) = (Options::default(), Deadline::new(None));
+
+        match _split(&mut span_boxes(&spans), &spans, &lines, &opts, &deadline, 0) {
+            Node::Grid { ref x, .. } => assert_eq!(x.len(), 1),
+            ref node => panic!("expected two columns, got {:?}", node)
+        }
+        // too deep: the boxes are only grouped into lines
+        match _split(&mut span_boxes(&spans), &spans, &lines, &opts, &deadline, MAX_SPLIT_DEPTH) {
+            Node::Final { ref indices } => assert_eq!(indices, &[0, 1]),
+            ref node => panic!("expected a single line, got {:?}", node)
+        }
+    }
+
+    #[test]
+    fn many_stacked_lines() {
+        // gaps of varying size, so the lines are split off in nested groups
+        let mut y = 0.0;
+        let spans: Vec<TextSpan> = (0 .. 5000).map(|i: u32| {
+            let s = span(&format!("w{}", i), 0.0, y, 10.0);
+            y += 12.0 + 2.0 * (i + 1).trailing_zeros() as f32;
+            s
+        }).collect();
+        let flow = layout(&spans, &[]);
+        let words: Vec<&str> = flow.lines().flat_map(|l| l.words.iter()).map(|w| w.text.as_str()).collect();
+        assert_eq!(words, spans.iter().map(|s| s.text.as_str()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn class_cache_ignores_order() {
+        let spans = [span("12", 0.0, 0.0, 10.0), span("34", 20.0, 0.0, 10.0), span("text", 0.0, 20.0, 10.0)];
+        let styles = StyleClassifier::default();
+        let cache = ClassCache::default();
+        assert_eq!(cache.classify([1, 0].into_iter(), &spans, &styles), Class::Number);
+        assert_eq!(cache.classes.borrow().len(), 1);
+        assert_eq!(cache.classify([0, 1].into_iter(), &spans, &styles), Class::Number);
+        assert_eq!(cache.classes.borrow().len(), 1);
+        assert_ne!(cache.classify([2].into_iter(), &spans, &styles), Class::Number);
+        assert_eq!(cache.classes.borrow().len(), 2);
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(layout(&[], &[]).runs.len(), 0);
+        assert!(matches!(overlapping_lines(&mut []), Node::Final { ref indices } if indices.is_empty()));
+        // a line that only holds empty spans
+        let flow = layout(&[span("", 0.0, 0.0, 10.0), span("", 0.0, 20.0, 10.0)], &[]);
+        assert!(flow.runs.iter().all(|r| r.lines.iter().all(|l| l.words.iter().all(|w| w.text.is_empty()))));
+    }
+
+    #[test]
+    fn lines_of_zero_height_boxes() {
+        let flat = |x, y| (RectF::new(Vector2F::new(x, y), Vector2F::new(10.0, 0.0)), 0);
+        let mut boxes: Vec<_> = [flat(20.0, 100.2), flat(0.0, 100.0), flat(0.0, 112.0), flat(20.0, 112.0)]
+            .into_iter().enumerate().map(|(i, (r, _))| (r, i)).collect();
+        match overlapping_lines(&mut boxes) {
+            Node::Grid { cells, .. } => {
+                let lines: Vec<_> = cells.iter().map(|c| match c {
+                    Node::Final { indices } => indices.clone(),
+                    _ => panic!("not a line")
+                }).collect();
+                assert_eq!(lines, [vec![1, 0], vec![2, 3]]);
+            }
+            _ => panic!("expected two lines")
+        }
+    }
+
+    #[test]
+    fn cells_in_a_gap_snap_to_the_nearest_column() {
+        let span = |start, end| Span::new(start, end).unwrap();
+        let columns = [span(0.0, 20.0), span(50.0, 70.0), span(100.0, 120.0)];
+        assert_eq!(column_range(&columns, span(5.0, 15.0)), (0, 0));
+        assert_eq!(column_range(&columns, span(10.0, 60.0)), (0, 1));
+        assert_eq!(column_range(&columns, span(25.0, 35.0)), (0, 0));
+        assert_eq!(column_range(&columns, span(85.0, 95.0)), (2, 2));
+    }
+
+    #[test]
+    fn x_y_ratio_tips_the_split() {
+        // columns 20 apart, rows 8 apart
+        let spans = grid_spans(&[0.0, 18.0], &[0.0, 40.0]);
+        let lines = analyze_lines(&[], 10.0);
+        let deadline = Deadline::new(None);
+        let gaps = |opts: &Options| match split(&mut span_boxes(&spans), &spans, &lines, opts, &deadline) {
+            Node::Grid { ref x, ref y, .. } => (x.len(), y.len()),
+            ref node => panic!("expected a grid, got {:?}", node)
+        };
+        assert_eq!(gaps(&Options::default()), (1, 0));
+        assert_eq!(gaps(&Options::default().x_y_ratio(8.0)), (0, 1));
+    }
+
+    #[test]
+    fn expected_columns_use_the_widest_gaps() {
+        assert_eq!(widest_gaps(&[(20.0, 40.0), (60.0, 100.0), (120.0, 130.0)], 2), [30.0, 80.0]);
+
+        let spans = grid_spans(&[0.0], &[0.0, 40.0, 100.0]);
+        let lines = analyze_lines(&[], 10.0);
+        let deadline = Deadline::new(None);
+        let columns = |opts: &Options| match split(&mut span_boxes(&spans), &spans, &lines, opts, &deadline) {
+            Node::Grid { x, .. } => x,
+            node => panic!("expected columns, got {:?}", node)
+        };
+        assert_eq!(columns(&Options::default()), [30.0, 80.0]);
+        assert_eq!(columns(&Options::default().expected_columns(Some(2))), [80.0]);
+    }
+
+    #[test]
+    fn content_stream_order() {
+        let mut spans = vec![span("World", 0.0, 100.0, 10.0), span("Hello", 0.0, 0.0, 10.0), span("there", 40.0, 0.0, 10.0)];
+        for (span, op_nr) in spans.iter_mut().zip([2, 0, 1]) {
+            span.op_nr = op_nr;
+        }
+        let mut flow = Flow::new();
+        content_order(&mut flow, &spans, &Options::default());
+        assert_eq!(flow.to_text(), "Hello there\n\nWorld\n");
+
+        // jumping back to the left starts a new line, even at the same height
+        assert_eq!(stream_lines(&spans, &[2, 1]), [vec![2], vec![1]]);
+    }
+
+    #[test]
+    fn paragraph_style_and_indent() {
+        let mut spans = vec![];
+        for (n, x) in [10.0, 0.0, 0.0, 10.0, 0.0].into_iter().enumerate() {
+            let y = 12.0 * n as f32;
+            spans.extend([span("some", x, y, 10.0), span("text", x + 25.0, y, 10.0)]);
+        }
+        let paragraphs = |opts: Options| -> Vec<usize> {
+            layout_with(&spans, &[], &opts.strip_header_footer(false)).runs.iter().map(|r| r.lines.len()).collect()
+        };
+        // first lines indented by one line height
+        assert_eq!(paragraphs(Options::default()), [3, 2]);
+        assert_eq!(paragraphs(Options::default().paragraph_indent(1.5)), [5]);
+        assert_eq!(paragraphs(Options::default().paragraph_style(Some(ParagraphStyle::Hanging))), [1, 1, 2, 1]);
+    }
+}
@@ -1,4 +1,9 @@
-use pathfinder_geometry::rect::RectF;
+use std::cell::Cell;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use pathfinder_geometry::{rect::RectF, vector::Vector2F};
+use regex::Regex;
 use serde::{Serialize, Deserialize};
 
 
@@ -6,6 +11,35 @@ pub fn is_number(s: &str) -> bool {
     s.len() > 0 && s.chars().all(|c| ('0' ..= '9').contains(&c))
 }
 
+/// Whether `text` is a page number: "3", "- 3 -", "Page 3", "3 / 20", "Page 3 of 20"
+/// or a lowercase roman numeral like "xiv".
+pub fn looks_like_page_number(text: &str) -> bool {
+    let text = text.trim().trim_matches(|c: char| matches!(c, '-' | '–' | '—') || c.is_whitespace());
+    let lower = text.to_lowercase();
+    let text = lower.strip_prefix("page").map(|t| t.trim_start()).unwrap_or(&lower);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    match words[..] {
+        [n] => match n.split_once('/') {
+            Some((a, b)) => is_number(a) && is_number(b),
+            None => is_number(n) || is_roman(n),
+        },
+        [a, "/", b] | [a, "of", b] => is_number(a) && is_number(b),
+        _ => false
+    }
+}
+
+/// a lowercase roman numeral below 400, so words like "civil" don't count
+fn is_roman(s: &str) -> bool {
+    static ROMAN: OnceLock<Regex> = OnceLock::new();
+    let re = ROMAN.get_or_init(|| Regex::new("^c{0,3}(xc|xl|l?x{0,3})(ix|iv|v?i{0,3})$").unwrap());
+    s.len() > 0 && re.is_match(s)
+}
+
+/// no coordinate is NaN or infinite
+pub fn is_finite(r: RectF) -> bool {
+    r.min_x().is_finite() && r.min_y().is_finite() && r.max_x().is_finite() && r.max_y().is_finite()
+}
+
 pub fn avg(iter: impl Iterator<Item=f32>) -> Option<f32> {
     let mut count = 0;
     let mut sum = 0.;
@@ -27,7 +61,7 @@ pub enum Tri {
     Unknown,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[derive(Serialize, Deserialize)]
 #[repr(C)]
 pub struct Rect {
@@ -36,6 +70,16 @@ pub struct Rect {
     pub w: f32,
     pub h: f32
 }
+impl Rect {
+    /// the smallest rect containing both
+    pub fn union(self, other: Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let max_x = (self.x + self.w).max(other.x + other.w);
+        let max_y = (self.y + self.h).max(other.y + other.h);
+        Rect { x, y, w: max_x - x, h: max_y - y }
+    }
+}
 impl From<RectF> for Rect {
     fn from(r: RectF) -> Self {
         Rect {
@@ -47,8 +91,99 @@ impl From<RectF> for Rect {
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
+/// Turns a page with vertical writing into one with horizontal writing, so the layout
+/// analysis can stay the same.
+///
+/// Columns running top to bottom become lines running left to right, and the columns
+/// from right to left become lines from top to bottom.
+pub struct Rotation {
+    /// `min_x + max_x` of the page
+    x_sum: f32,
+}
+impl Rotation {
+    pub fn new(page: RectF) -> Self {
+        Rotation { x_sum: page.min_x() + page.max_x() }
+    }
+    /// page to layout coordinates
+    pub fn rect(&self, r: RectF) -> RectF {
+        RectF::new(Vector2F::new(r.min_y(), self.x_sum - r.max_x()), Vector2F::new(r.height(), r.width()))
+    }
+    /// page to layout coordinates
+    pub fn line(&self, [x1, y1, x2, y2]: [f32; 4]) -> [f32; 4] {
+        [y1, self.x_sum - x1, y2, self.x_sum - x2]
+    }
+    /// layout to page coordinates
+    pub fn unrotate(&self, r: Rect) -> Rect {
+        Rect { x: self.x_sum - (r.y + r.h), y: r.x, w: r.h, h: r.w }
+    }
+    /// layout to page coordinates
+    pub fn unrotate_rect(&self, r: RectF) -> RectF {
+        RectF::new(Vector2F::new(self.x_sum - r.max_y(), r.min_x()), Vector2F::new(r.height(), r.width()))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CellContent {
     pub text: String,
     pub rect: Rect,
-}
\ No newline at end of file
+    pub row: u32,
+    pub col: u32,
+    pub rowspan: u32,
+    pub colspan: u32,
+}
+
+/// A point in time after which the expensive parts of the pipeline give up.
+///
+/// Once `check` has observed the deadline, it stays expired, so the caller can
+/// ask afterwards whether the result is partial.
+pub struct Deadline {
+    at: Option<Instant>,
+    expired: Cell<bool>,
+}
+impl Deadline {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Deadline {
+            // a timeout too long to represent is no deadline at all
+            at: timeout.and_then(|t| Instant::now().checked_add(t)),
+            expired: Cell::new(false),
+        }
+    }
+    /// returns true if the deadline has passed
+    pub fn check(&self) -> bool {
+        if self.expired.get() {
+            return true;
+        }
+        match self.at {
+            Some(at) if Instant::now() >= at => {
+                self.expired.set(true);
+                true
+            }
+            _ => false
+        }
+    }
+    pub fn expired(&self) -> bool {
+        self.expired.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadline_beyond_the_clock() {
+        let deadline = Deadline::new(Some(Duration::MAX));
+        assert!(!deadline.check());
+        assert!(Deadline::new(Some(Duration::ZERO)).check());
+    }
+
+    #[test]
+    fn page_numbers() {
+        for text in ["3", "- 3 -", "Page 3", "3 / 20", "Page 3 of 20", "xiv", "xl", "cxc", "- iv -"] {
+            assert!(looks_like_page_number(text), "{:?}", text);
+        }
+        for text in ["civil", "ill", "vici", "civic", "iiii", "vx", "Chapter 3"] {
+            assert!(!looks_like_page_number(text), "{:?}", text);
+        }
+    }
+}